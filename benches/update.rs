@@ -0,0 +1,95 @@
+//! How `World::update` scales with the number of threads on a large world, and how little a
+//! world that has settled costs.
+//!
+//! Run with `cargo bench --bench update`.
+
+use cgmath::Vector2;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use powpowder::{World, BACKGROUND_COLOR};
+use rayon::ThreadPoolBuilder;
+
+const WORLD_WIDTH: u32 = 1024;
+const WORLD_HEIGHT: u32 = 768;
+const TICKS: u64 = 5;
+
+/// A world whose top half is a mix of sand and water about to fall down, so every chunk is busy.
+fn falling_world() -> World {
+    let mut world = World::with_seed(BACKGROUND_COLOR, WORLD_WIDTH, WORLD_HEIGHT, 0);
+    let sand = world.materials().find("sand").unwrap();
+    let water = world.materials().find("water").unwrap();
+
+    for y in 0..WORLD_HEIGHT / 2 {
+        for x in 0..WORLD_WIDTH {
+            let material = if (x / 4 + y / 4) % 2 == 0 { sand } else { water };
+            let tile = world.create_tile(material);
+            world.place_tile(Vector2::new(x, y), tile);
+        }
+    }
+
+    world
+}
+
+/// A world whose bottom quarter is sand under a layer of water, left until nothing moves anymore.
+fn settled_world() -> World {
+    let mut world = World::with_seed(BACKGROUND_COLOR, WORLD_WIDTH, WORLD_HEIGHT, 0);
+    let sand = world.materials().find("sand").unwrap();
+    let water = world.materials().find("water").unwrap();
+
+    for y in WORLD_HEIGHT * 3 / 4..WORLD_HEIGHT {
+        for x in 0..WORLD_WIDTH {
+            let material = if y < WORLD_HEIGHT * 7 / 8 { water } else { sand };
+            let tile = world.create_tile(material);
+            world.place_tile(Vector2::new(x, y), tile);
+        }
+    }
+
+    let mut tick = 0;
+    while tick < 2 || world.active_chunk_count() > 0 {
+        tick += 1;
+        world.update(1.0 / 60.0, tick);
+    }
+
+    world
+}
+
+fn run_ticks(world: &mut World) {
+    for tick in 1..=TICKS {
+        world.update(1.0 / 60.0, tick);
+    }
+}
+
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    group.sample_size(10);
+
+    group.bench_function("single_threaded", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut world = falling_world();
+                world.set_multithreaded(false);
+                world
+            },
+            run_ticks,
+            BatchSize::LargeInput
+        )
+    });
+
+    for &threads in &[1, 2, 4, 8] {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+
+        group.bench_with_input(BenchmarkId::new("threads", threads), &threads, |b, _| {
+            b.iter_batched_ref(falling_world, |world| pool.install(|| run_ticks(world)), BatchSize::LargeInput)
+        });
+    }
+
+    group.finish();
+}
+
+fn settled(c: &mut Criterion) {
+    let mut world = settled_world();
+
+    c.bench_function("settled", |b| b.iter(|| run_ticks(&mut world)));
+}
+
+criterion_group!(benches, update, settled);
+criterion_main!(benches);
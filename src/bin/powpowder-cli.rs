@@ -0,0 +1,309 @@
+//! Runs a simulation without a window and writes out the results.
+//!
+//! Run `powpowder-cli --help` for the available options.
+
+use powpowder::{World, MaterialRegistry, BACKGROUND_COLOR};
+use powpowder::import::{Palette, ImageFit};
+use powpowder::export::{PngSequenceExporter, GifRecorder};
+use powpowder::schedule::ScanOrder;
+use powpowder::boundary::{Boundaries, BoundaryMode};
+use powpowder::save::MAX_WORLD_SIZE;
+use rayon::ThreadPoolBuilder;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+Usage: powpowder-cli [options]
+
+Options:
+    --width <tiles>          World width, ignored when loading a saved world (default 400)
+    --height <tiles>         World height, ignored when loading a saved world (default 300)
+    --seed <number>          Seed for the world's random number generator, ignored when loading a saved world (default 0)
+    --ticks <number>         Number of ticks to simulate (default 600)
+    --tick-rate <number>     Ticks per simulated second (default 60)
+    --threads <number>       Threads to update the world on, 0 for one per core (default 0)
+    --scan-order <order>     Order tiles are updated in: columns, bottom-up, alternating or random (default alternating)
+    --boundaries <modes>     What happens at the edges: wall, void, wrap or mirror, either one for every edge
+                             or four separated by commas for the left, right, top and bottom edge (default wall)
+    --scene <file>           Initial scene, either a saved world or a PNG/GIF image
+    --materials <file>       Materials file to use instead of the built-in materials
+    --output <file>          Save the final world to this file
+    --snapshot <file>        Write a PNG of the final world to this file
+    --summary <file>         Write the JSON summary to this file instead of stdout
+    --frames <directory>     Write a PNG every --frame-interval ticks to this directory
+    --gif <file>             Record an animated GIF of the run, a frame every --frame-interval ticks
+    --frame-interval <ticks> Ticks between exported frames (default 5)
+    --scale <factor>         Integer upscaling of exported images (default 1)
+    -h, --help               Print this message";
+
+struct Options {
+    world_width: u32,
+    world_height: u32,
+    seed: u64,
+    ticks: u64,
+    tick_rate: f32,
+    threads: usize,
+    scan_order: ScanOrder,
+    boundaries: Option<Boundaries>,
+    scene: Option<PathBuf>,
+    materials: Option<PathBuf>,
+    output: Option<PathBuf>,
+    snapshot: Option<PathBuf>,
+    summary: Option<PathBuf>,
+    frames: Option<PathBuf>,
+    gif: Option<PathBuf>,
+    frame_interval: u64,
+    scale: u32
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            world_width: 400,
+            world_height: 300,
+            seed: 0,
+            ticks: 600,
+            tick_rate: 60.0,
+            threads: 0,
+            scan_order: ScanOrder::Alternating,
+            boundaries: None,
+            scene: None,
+            materials: None,
+            output: None,
+            snapshot: None,
+            summary: None,
+            frames: None,
+            gif: None,
+            frame_interval: 5,
+            scale: 1
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+
+            match arg.as_str() {
+                "--width" => options.world_width = parse_number(&arg, value()?)?,
+                "--height" => options.world_height = parse_number(&arg, value()?)?,
+                "--seed" => options.seed = parse_number(&arg, value()?)?,
+                "--ticks" => options.ticks = parse_number(&arg, value()?)?,
+                "--tick-rate" => options.tick_rate = parse_number(&arg, value()?)?,
+                "--threads" => options.threads = parse_number(&arg, value()?)?,
+                "--scan-order" => options.scan_order = parse_scan_order(value()?)?,
+                "--boundaries" => options.boundaries = Some(parse_boundaries(value()?)?),
+                "--scene" => options.scene = Some(value()?.into()),
+                "--materials" => options.materials = Some(value()?.into()),
+                "--output" => options.output = Some(value()?.into()),
+                "--snapshot" => options.snapshot = Some(value()?.into()),
+                "--summary" => options.summary = Some(value()?.into()),
+                "--frames" => options.frames = Some(value()?.into()),
+                "--gif" => options.gif = Some(value()?.into()),
+                "--frame-interval" => options.frame_interval = parse_number(&arg, value()?)?,
+                "--scale" => options.scale = parse_number(&arg, value()?)?,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => return Err(format!("unknown option {}", arg))
+            }
+        }
+
+        if options.world_width == 0 || options.world_height == 0 {
+            return Err("world size must be at least 1x1".to_string());
+        }
+        if options.world_width > MAX_WORLD_SIZE || options.world_height > MAX_WORLD_SIZE {
+            return Err(format!("world size must be at most {}x{}", MAX_WORLD_SIZE, MAX_WORLD_SIZE));
+        }
+        if !(options.tick_rate > 0.0 && options.tick_rate.is_finite()) {
+            return Err("tick rate must be positive".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_number<T: FromStr>(arg: &str, value: String) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value for {}: {}", arg, value))
+}
+
+fn parse_scan_order(value: String) -> Result<ScanOrder, String> {
+    match value.as_str() {
+        "columns" => Ok(ScanOrder::Columns),
+        "bottom-up" => Ok(ScanOrder::BottomUp),
+        "alternating" => Ok(ScanOrder::Alternating),
+        "random" => Ok(ScanOrder::Random),
+        _ => Err(format!("invalid value for --scan-order: {}", value))
+    }
+}
+
+fn parse_boundaries(value: String) -> Result<Boundaries, String> {
+    let modes = value.split(',')
+        .map(|mode| match mode {
+            "wall" => Ok(BoundaryMode::Wall),
+            "void" => Ok(BoundaryMode::Void),
+            "wrap" => Ok(BoundaryMode::Wrap),
+            "mirror" => Ok(BoundaryMode::Mirror),
+            _ => Err(format!("invalid value for --boundaries: {}", value))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match modes[..] {
+        [mode] => Ok(Boundaries::all(mode)),
+        [left, right, top, bottom] => Ok(Boundaries { left, right, top, bottom }),
+        _ => Err(format!("--boundaries takes one or four modes, not {}", modes.len()))
+    }
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    // The world's results don't depend on the number of threads, only its speed does
+    if let Err(error) = ThreadPoolBuilder::new().num_threads(options.threads).build_global() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+
+    if let Err(error) = run(options) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let mut world = create_world(&options)?;
+    world.set_scan_order(options.scan_order);
+    // A saved world keeps its own boundaries unless they're given
+    if let Some(boundaries) = options.boundaries {
+        world.set_boundaries(boundaries);
+    }
+
+    let mut png_exporter = match &options.frames {
+        Some(directory) => Some(PngSequenceExporter::new(directory, options.frame_interval)?.with_scale(options.scale)),
+        None => None
+    };
+    let mut gif_recorder = match &options.gif {
+        Some(path) => Some(GifRecorder::create(path, options.frame_interval, options.tick_rate)?.with_scale(options.scale)),
+        None => None
+    };
+
+    let tick_duration = 1.0 / options.tick_rate;
+
+    for tick in 1..=options.ticks {
+        world.update(tick_duration, tick);
+
+        if let Some(png_exporter) = &mut png_exporter {
+            png_exporter.capture(&world, tick)?;
+        }
+        if let Some(gif_recorder) = &mut gif_recorder {
+            gif_recorder.capture(&world, tick)?;
+        }
+    }
+
+    // Finish the GIF before reporting success
+    if let Some(gif_recorder) = gif_recorder {
+        gif_recorder.finish()?;
+    }
+
+    if let Some(path) = &options.output {
+        world.save_to(BufWriter::new(File::create(path)?))?;
+    }
+    if let Some(path) = &options.snapshot {
+        world.render_image_scaled(options.scale).save(path)?;
+    }
+
+    let summary = summary_json(&world, &options);
+    match &options.summary {
+        Some(path) => {
+            let mut file = File::create(path)?;
+            writeln!(file, "{}", summary)?;
+        }
+        None => println!("{}", summary)
+    }
+
+    Ok(())
+}
+
+fn create_world(options: &Options) -> Result<World, Box<dyn Error>> {
+    let materials = match &options.materials {
+        Some(path) => Arc::new(MaterialRegistry::load(path)?),
+        None => MaterialRegistry::builtin()
+    };
+
+    let scene = match &options.scene {
+        Some(scene) => scene,
+        None => return Ok(World::with_materials(BACKGROUND_COLOR, options.world_width, options.world_height, options.seed, materials))
+    };
+
+    if is_image(scene) {
+        let palette = Palette::standard(&materials);
+        let mut world = World::with_materials(BACKGROUND_COLOR, options.world_width, options.world_height, options.seed, materials);
+        world.import_image_file(scene, &palette, ImageFit::Resize)?;
+
+        Ok(world)
+    } else {
+        // A saved world brings its own size and RNG state along
+        Ok(World::load_from(BufReader::new(File::open(scene)?), materials)?)
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => ["png", "gif"].contains(&extension.to_ascii_lowercase().as_str()),
+        None => false
+    }
+}
+
+fn summary_json(world: &World, options: &Options) -> String {
+    let materials = world.materials();
+
+    let mut empty_count = 0;
+    let mut material_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for &tile in world.tiles() {
+        match tile.get_material() {
+            Some(material) => *material_counts.entry(materials.get(material).id.as_str()).or_insert(0) += 1,
+            None => empty_count += 1
+        }
+    }
+
+    let material_counts = material_counts.iter()
+        .map(|(id, count)| format!("{}: {}", json_string(id), count))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "{{\"width\": {}, \"height\": {}, \"seed\": {}, \"ticks\": {}, \"particles\": {}, \"empty\": {}, \"materials\": {{{}}}}}",
+        world.world_width, world.world_height, world.seed(), options.ticks, world.particles().len(), empty_count, material_counts
+    )
+}
+
+/// Quotes `value` as a JSON string, escaping what JSON doesn't allow as is.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for character in value.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if (character as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            character => quoted.push(character)
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,245 @@
+//! What happens at the edges of the world.
+//!
+//! Each edge has its own `BoundaryMode`. Tiles looking past an edge see what the mode says is
+//! there and moving past it does what looking suggested: a wall is solid, a void is empty and
+//! swallows whatever enters it, and wrapping and mirroring lead back into the world, to the
+//! opposite edge or the cells just inside. Particles follow the same rules.
+//!
+//! Reactions and heat stay within the world whatever the boundaries are.
+
+use crate::world::World;
+use crate::particle::Particle;
+use cgmath::Vector2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum BoundaryMode {
+    /// A solid wall nothing gets through.
+    #[default]
+    Wall,
+    /// Empty space that deletes tiles and particles falling into it.
+    Void,
+    /// Leads to the opposite edge, like on a torus.
+    Wrap,
+    /// Reflects the world, so the cells past the edge look like the ones just inside it.
+    /// Particles hitting it collide with their reflection and land.
+    Mirror
+}
+
+/// The boundary mode of each edge of a world.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Boundaries {
+    pub left: BoundaryMode,
+    pub right: BoundaryMode,
+    pub top: BoundaryMode,
+    pub bottom: BoundaryMode
+}
+
+/// Where a position relative to the world ends up, see `Boundaries::resolve`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resolved {
+    /// The cell it leads to.
+    Inside(Vector2<u32>),
+    Wall,
+    Void
+}
+
+/// What became of a particle at the edges, see `Boundaries::constrain_particle`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParticleBoundary {
+    /// It is within the world, possibly after being stopped or wrapped around.
+    Inside,
+    /// It hit a mirror and should land where it is.
+    Landed,
+    /// It fell into a void.
+    Gone
+}
+
+impl Boundaries {
+    /// The same mode on every edge.
+    pub fn all(mode: BoundaryMode) -> Boundaries {
+        Boundaries { left: mode, right: mode, top: mode, bottom: mode }
+    }
+
+    /// Resolves `position`, which may lie outside a world of `world_width` by `world_height`, to
+    /// the cell it leads to or to what lies past the edge.
+    pub fn resolve(&self, position: Vector2<i32>, world_width: u32, world_height: u32) -> Resolved {
+        if position.x >= 0 && position.y >= 0 && (position.x as u32) < world_width && (position.y as u32) < world_height {
+            return Resolved::Inside(Vector2::new(position.x as u32, position.y as u32));
+        }
+
+        let x = match resolve_axis(position.x, world_width, self.left, self.right) {
+            Ok(x) => x,
+            Err(resolved) => return resolved
+        };
+        let y = match resolve_axis(position.y, world_height, self.top, self.bottom) {
+            Ok(y) => y,
+            Err(resolved) => return resolved
+        };
+
+        Resolved::Inside(Vector2::new(x, y))
+    }
+
+    /// Brings a particle that moved past an edge back into a world of `world_width` by
+    /// `world_height`, or tells what else became of it.
+    pub fn constrain_particle(&self, particle: &mut Particle, world_width: u32, world_height: u32) -> ParticleBoundary {
+        let x = constrain_axis(&mut particle.position.x, &mut particle.velocity.x, world_width, self.left, self.right);
+        let y = constrain_axis(&mut particle.position.y, &mut particle.velocity.y, world_height, self.top, self.bottom);
+
+        match (x, y) {
+            (ParticleBoundary::Gone, _) | (_, ParticleBoundary::Gone) => ParticleBoundary::Gone,
+            (ParticleBoundary::Landed, _) | (_, ParticleBoundary::Landed) => ParticleBoundary::Landed,
+            _ => ParticleBoundary::Inside
+        }
+    }
+
+    /// Whether cells on opposite edges are neighbours, horizontally and vertically.
+    pub(crate) fn wraps(&self) -> Vector2<bool> {
+        Vector2::new(
+            self.left == BoundaryMode::Wrap && self.right == BoundaryMode::Wrap,
+            self.top == BoundaryMode::Wrap && self.bottom == BoundaryMode::Wrap
+        )
+    }
+}
+
+fn resolve_axis(coordinate: i32, size: u32, low: BoundaryMode, high: BoundaryMode) -> Result<u32, Resolved> {
+    let size = size as i32;
+    let mode = if coordinate < 0 {
+        low
+    } else if coordinate >= size {
+        high
+    } else {
+        return Ok(coordinate as u32);
+    };
+
+    match mode {
+        BoundaryMode::Wall => Err(Resolved::Wall),
+        BoundaryMode::Void => Err(Resolved::Void),
+        BoundaryMode::Wrap => Ok(coordinate.rem_euclid(size) as u32),
+        BoundaryMode::Mirror => {
+            let mirrored = if coordinate < 0 { -coordinate - 1 } else { 2 * size - coordinate - 1 };
+            Ok(mirrored.clamp(0, size - 1) as u32)
+        }
+    }
+}
+
+/// How close to the far edge a particle can get while still being over the last cell.
+const EDGE_MARGIN: f32 = 0.001;
+
+fn constrain_axis(position: &mut f32, velocity: &mut f32, size: u32, low: BoundaryMode, high: BoundaryMode) -> ParticleBoundary {
+    let size = size as f32;
+    let mode = if *position < 0.0 {
+        low
+    } else if *position >= size {
+        high
+    } else {
+        return ParticleBoundary::Inside;
+    };
+
+    match mode {
+        BoundaryMode::Wall => {
+            *position = position.clamp(0.0, size - EDGE_MARGIN);
+            *velocity = 0.0;
+            ParticleBoundary::Inside
+        }
+        BoundaryMode::Void => ParticleBoundary::Gone,
+        BoundaryMode::Wrap => {
+            // `rem_euclid` can round up to `size` itself for tiny negative positions
+            *position = position.rem_euclid(size);
+            if *position >= size {
+                *position = 0.0;
+            }
+            ParticleBoundary::Inside
+        }
+        BoundaryMode::Mirror => {
+            *position = position.clamp(0.0, size - EDGE_MARGIN);
+            *velocity = 0.0;
+            ParticleBoundary::Landed
+        }
+    }
+}
+
+impl World {
+    pub fn boundaries(&self) -> Boundaries {
+        self.boundaries
+    }
+
+    /// Changes what happens at the edges of the world from the next tick on.
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.boundaries = boundaries;
+        self.chunks.set_wraps(boundaries.wraps());
+        // Tiles along the edges may be able to move now
+        self.chunks.wake_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BoundaryMode::*;
+
+    #[test]
+    fn resolves_past_each_mode() {
+        assert_eq!(resolve_axis(3, 10, Wall, Wall), Ok(3));
+        assert_eq!(resolve_axis(-1, 10, Wall, Void), Err(Resolved::Wall));
+        assert_eq!(resolve_axis(10, 10, Wall, Void), Err(Resolved::Void));
+    }
+
+    #[test]
+    fn wraps_to_the_opposite_edge() {
+        assert_eq!(resolve_axis(-1, 10, Wrap, Wrap), Ok(9));
+        assert_eq!(resolve_axis(-11, 10, Wrap, Wrap), Ok(9));
+        assert_eq!(resolve_axis(10, 10, Wrap, Wrap), Ok(0));
+        assert_eq!(resolve_axis(23, 10, Wrap, Wrap), Ok(3));
+    }
+
+    #[test]
+    fn mirrors_the_cells_just_inside() {
+        assert_eq!(resolve_axis(-1, 10, Mirror, Mirror), Ok(0));
+        assert_eq!(resolve_axis(-3, 10, Mirror, Mirror), Ok(2));
+        assert_eq!(resolve_axis(10, 10, Mirror, Mirror), Ok(9));
+        assert_eq!(resolve_axis(12, 10, Mirror, Mirror), Ok(7));
+        // Further away than the world is wide stays within it
+        assert_eq!(resolve_axis(-25, 10, Mirror, Mirror), Ok(9));
+        assert_eq!(resolve_axis(35, 10, Mirror, Mirror), Ok(0));
+    }
+
+    #[test]
+    fn resolves_corners_through_both_edges() {
+        let boundaries = Boundaries { left: Wrap, right: Wrap, top: Mirror, bottom: Void };
+        assert_eq!(boundaries.resolve(Vector2::new(-1, -2), 10, 8), Resolved::Inside(Vector2::new(9, 1)));
+        assert_eq!(boundaries.resolve(Vector2::new(-1, 8), 10, 8), Resolved::Void);
+    }
+
+    #[test]
+    fn wrapping_stays_below_the_far_edge() {
+        let (mut position, mut velocity) = (-1e-9, 5.0);
+        assert_eq!(constrain_axis(&mut position, &mut velocity, 10, Wrap, Wrap), ParticleBoundary::Inside);
+        assert!((0.0..10.0).contains(&position), "wrapped to {}", position);
+        assert_eq!(velocity, 5.0);
+
+        let mut position = 12.5;
+        constrain_axis(&mut position, &mut velocity, 10, Wrap, Wrap);
+        assert_eq!(position, 2.5);
+    }
+
+    #[test]
+    fn walls_and_mirrors_stop_particles() {
+        let (mut position, mut velocity) = (11.0, 3.0);
+        assert_eq!(constrain_axis(&mut position, &mut velocity, 10, Wall, Wall), ParticleBoundary::Inside);
+        assert!(position < 10.0 && position as u32 == 9);
+        assert_eq!(velocity, 0.0);
+
+        let (mut position, mut velocity) = (-0.5, -3.0);
+        assert_eq!(constrain_axis(&mut position, &mut velocity, 10, Mirror, Mirror), ParticleBoundary::Landed);
+        assert_eq!((position, velocity), (0.0, 0.0));
+    }
+
+    #[test]
+    fn voids_swallow_particles() {
+        let (mut position, mut velocity) = (-0.5, -3.0);
+        assert_eq!(constrain_axis(&mut position, &mut velocity, 10, Void, Wall), ParticleBoundary::Gone);
+
+        let mut position = 4.0;
+        assert_eq!(constrain_axis(&mut position, &mut velocity, 10, Void, Void), ParticleBoundary::Inside);
+    }
+}
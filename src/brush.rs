@@ -0,0 +1,84 @@
+use crate::world::World;
+use crate::tile::Tile;
+use crate::particle::Particle;
+use crate::material::{MaterialId, MaterialRegistry, BrushKind};
+use cgmath::Vector2;
+use rand::Rng;
+
+/// Draws tiles of a material into a world, the way its `BrushKind` describes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Brush {
+    /// `None` erases.
+    material: Option<MaterialId>,
+    kind: BrushKind
+}
+
+impl Brush {
+    /// The brush the material's definition asks for.
+    pub fn for_material(materials: &MaterialRegistry, material: MaterialId) -> Brush {
+        Brush {
+            material: Some(material),
+            kind: materials.get(material).brush
+        }
+    }
+
+    /// A brush that clears the square of tiles within `radius`.
+    pub fn eraser(radius: u32) -> Brush {
+        Brush {
+            material: None,
+            kind: BrushKind::Fill { radius }
+        }
+    }
+
+    pub fn material(&self) -> Option<MaterialId> {
+        self.material
+    }
+
+    pub fn kind(&self) -> BrushKind {
+        self.kind
+    }
+
+    /// Applies one tick's worth of the brush at `position`. Random colors and velocities come
+    /// from the world's RNG, so painting stays deterministic.
+    pub fn apply(&self, world: &mut World, position: Vector2<u32>) {
+        match self.kind {
+            BrushKind::Fill { radius } => {
+                let radius = radius as i64;
+
+                for x in -radius..=radius {
+                    for y in -radius..=radius {
+                        let nx = position.x as i64 + x;
+                        let ny = position.y as i64 + y;
+
+                        if nx < 0 || ny < 0 || nx >= world.world_width as i64 || ny >= world.world_height as i64 {
+                            continue;
+                        }
+
+                        let tile = self.create_tile(world);
+                        world.place_tile(Vector2::new(nx as u32, ny as u32), tile);
+                    }
+                }
+            }
+            BrushKind::Spray { amount, speed } => {
+                for _ in 0..amount {
+                    let tile = self.create_tile(world);
+                    if tile == Tile::Empty {
+                        continue;
+                    }
+
+                    let rng = world.rng();
+                    let velocity = Vector2::new(rng.gen_range(-speed, speed), rng.gen_range(-speed, speed));
+
+                    world.add_particle(Particle::new(tile, position.cast().unwrap(), velocity));
+                }
+            }
+        }
+    }
+
+    fn create_tile(&self, world: &mut World) -> Tile {
+        match self.material {
+            Some(material) => world.create_tile(material),
+            None => Tile::Empty
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! A view onto part of a world, magnified by a whole number.
+//!
+//! The camera maps a screen, e.g. a frontend's pixel buffer, onto the world: every tile it shows
+//! covers `zoom` by `zoom` screen pixels, so individual grains stay sharp. Worlds are drawn by
+//! `World::render` into a frame of their own size first, which `Camera::render` then copies the
+//! visible part of onto the screen.
+
+use cgmath::Vector2;
+
+/// Largest magnification a camera zooms to.
+pub const MAX_ZOOM: u32 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    /// The world position at the top left corner of the screen, in tiles.
+    position: Vector2<f32>,
+    zoom: u32,
+    /// Size of the screen in pixels.
+    screen_size: Vector2<u32>,
+    world_size: Vector2<u32>
+}
+
+impl Camera {
+    /// Creates a camera showing a world of `world_size` from its top left corner on a screen of
+    /// `screen_size`, unmagnified.
+    pub fn new(screen_size: Vector2<u32>, world_size: Vector2<u32>) -> Camera {
+        Camera {
+            position: Vector2::new(0.0, 0.0),
+            zoom: 1,
+            screen_size,
+            world_size
+        }
+    }
+
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    pub fn zoom(&self) -> u32 {
+        self.zoom
+    }
+
+    /// Changes the size of the screen and of the world it looks at, e.g. after the world was
+    /// resized, keeping the view within the world.
+    pub fn set_sizes(&mut self, screen_size: Vector2<u32>, world_size: Vector2<u32>) {
+        self.screen_size = screen_size;
+        self.world_size = world_size;
+        self.keep_within_world();
+    }
+
+    /// Changes the magnification to `zoom`, clamped to 1 to `MAX_ZOOM`, keeping the tile under
+    /// `screen_position` where it is.
+    pub fn zoom_around(&mut self, zoom: u32, screen_position: Vector2<f32>) {
+        let zoom = zoom.clamp(1, MAX_ZOOM);
+        let world_position = self.screen_to_world(screen_position);
+
+        self.zoom = zoom;
+        self.position = world_position - screen_position / zoom as f32;
+        self.keep_within_world();
+    }
+
+    /// Moves the view by `screen_offset` screen pixels, so what was shown there is shown at the
+    /// top left corner.
+    pub fn pan(&mut self, screen_offset: Vector2<f32>) {
+        self.position += screen_offset / self.zoom as f32;
+        self.keep_within_world();
+    }
+
+    /// The world position shown at `screen_position`, in tiles.
+    pub fn screen_to_world(&self, screen_position: Vector2<f32>) -> Vector2<f32> {
+        self.position_on_screen() + screen_position / self.zoom as f32
+    }
+
+    /// The tile shown at `screen_position`, `None` if that isn't within the world.
+    pub fn tile_at(&self, screen_position: Vector2<f32>) -> Option<Vector2<u32>> {
+        let world_position = self.screen_to_world(screen_position);
+
+        if world_position.x < 0.0 || world_position.y < 0.0 {
+            return None;
+        }
+
+        let tile_position = Vector2::new(world_position.x as u32, world_position.y as u32);
+        if tile_position.x >= self.world_size.x || tile_position.y >= self.world_size.y {
+            return None;
+        }
+
+        Some(tile_position)
+    }
+
+    /// Copies the visible part of `world_frame`, an RGBA frame of the whole world, onto `screen`,
+    /// an RGBA frame of the screen. Screen pixels beyond the world are left as they are.
+    pub fn render(&self, world_frame: &[u8], screen: &mut [u8]) {
+        let zoom = self.zoom as usize;
+        let position = self.position_on_screen() * self.zoom as f32;
+        // Where the screen starts on the world magnified by the zoom, in whole pixels
+        let offset = Vector2::new(position.x as usize, position.y as usize);
+
+        for (screen_y, row) in screen.chunks_exact_mut(self.screen_size.x as usize * 4).enumerate() {
+            let tile_y = (offset.y + screen_y) / zoom;
+            if tile_y >= self.world_size.y as usize {
+                break;
+            }
+
+            let world_row = &world_frame[tile_y * self.world_size.x as usize * 4..(tile_y + 1) * self.world_size.x as usize * 4];
+
+            for (screen_x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let tile_x = (offset.x + screen_x) / zoom;
+                if tile_x >= self.world_size.x as usize {
+                    break;
+                }
+
+                pixel.copy_from_slice(&world_row[tile_x * 4..tile_x * 4 + 4]);
+            }
+        }
+    }
+
+    /// The position snapped to whole screen pixels, so every tile covers exactly `zoom` of them.
+    fn position_on_screen(&self) -> Vector2<f32> {
+        // Nudged up a little so a position just below a pixel through rounding stays on it
+        let zoom = self.zoom as f32;
+        Vector2::new((self.position.x * zoom + 0.001).floor() / zoom, (self.position.y * zoom + 0.001).floor() / zoom)
+    }
+
+    fn keep_within_world(&mut self) {
+        let visible = Vector2::new(self.screen_size.x as f32, self.screen_size.y as f32) / self.zoom as f32;
+        let max = Vector2::new(
+            (self.world_size.x as f32 - visible.x).max(0.0),
+            (self.world_size.y as f32 - visible.y).max(0.0)
+        );
+
+        self.position = Vector2::new(self.position.x.clamp(0.0, max.x), self.position.y.clamp(0.0, max.y));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(Vector2::new(160, 120), Vector2::new(200, 150))
+    }
+
+    #[test]
+    fn zooming_keeps_the_tile_under_the_cursor() {
+        let mut camera = camera();
+        let cursor = Vector2::new(80.0, 60.0);
+        assert_eq!(camera.tile_at(cursor), Some(Vector2::new(80, 60)));
+
+        camera.zoom_around(4, cursor);
+        assert_eq!(camera.zoom(), 4);
+        assert_eq!(camera.position(), Vector2::new(60.0, 45.0));
+        assert_eq!(camera.tile_at(cursor), Some(Vector2::new(80, 60)));
+        // Each tile covers 4 by 4 pixels now
+        assert_eq!(camera.tile_at(cursor + Vector2::new(3.0, 3.0)), Some(Vector2::new(80, 60)));
+        assert_eq!(camera.tile_at(cursor + Vector2::new(4.0, -1.0)), Some(Vector2::new(81, 59)));
+    }
+
+    #[test]
+    fn zooming_stays_within_the_world() {
+        let mut camera = camera();
+        camera.zoom_around(100, Vector2::new(160.0, 120.0));
+        assert_eq!(camera.zoom(), MAX_ZOOM);
+        assert_eq!(camera.position(), Vector2::new(150.0, 112.5));
+        assert_eq!(camera.tile_at(Vector2::new(159.0, 119.0)), Some(Vector2::new(159, 119)));
+
+        // Zooming back out near the far corner can't show past it
+        camera.zoom_around(0, Vector2::new(0.0, 0.0));
+        assert_eq!(camera.zoom(), 1);
+        assert_eq!(camera.position(), Vector2::new(40.0, 30.0));
+    }
+
+    #[test]
+    fn no_tile_beyond_the_world() {
+        let camera = Camera::new(Vector2::new(160, 120), Vector2::new(100, 80));
+        assert_eq!(camera.tile_at(Vector2::new(99.5, 79.5)), Some(Vector2::new(99, 79)));
+        assert_eq!(camera.tile_at(Vector2::new(100.0, 10.0)), None);
+        assert_eq!(camera.tile_at(Vector2::new(10.0, 80.0)), None);
+        assert_eq!(camera.tile_at(Vector2::new(-0.5, 10.0)), None);
+    }
+}
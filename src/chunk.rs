@@ -0,0 +1,273 @@
+//! Dirty rectangles that let `World::update` skip the settled parts of a world.
+//!
+//! The world is split into square chunks, each with a rectangle around the cells that changed in
+//! it. Changing a tile marks it and its eight neighbours, since those may be able to move now,
+//! and a tick only visits the cells within what was marked during the two ticks before it. A
+//! chunk where nothing happened for two ticks falls asleep until a change nearby wakes it again.
+//!
+//! Tiles that could still do something without anything around them changing, e.g. because they
+//! are waiting on a chance to catch fire, keep their cell awake with `LiveTileApi::keep_awake`.
+//! Settled tiles don't count towards their next color, so asleep water stops shimmering.
+
+use crate::Color;
+use crate::world::World;
+use cgmath::Vector2;
+
+/// Width and height of a chunk in tiles.
+pub const CHUNK_SIZE: u32 = 32;
+
+/// A rectangle of cells, both corners included.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub min: Vector2<u32>,
+    pub max: Vector2<u32>
+}
+
+impl DirtyRect {
+    fn new(position: Vector2<u32>) -> DirtyRect {
+        DirtyRect { min: position, max: position }
+    }
+
+    fn include(&mut self, position: Vector2<u32>) {
+        self.min = Vector2::new(self.min.x.min(position.x), self.min.y.min(position.y));
+        self.max = Vector2::new(self.max.x.max(position.x), self.max.y.max(position.y));
+    }
+
+    fn union(self, other: DirtyRect) -> DirtyRect {
+        let mut union = self;
+        union.include(other.min);
+        union.include(other.max);
+        union
+    }
+}
+
+/// Keeps track of which cells of each chunk need updating.
+#[derive(Clone, Debug)]
+pub(crate) struct Chunks {
+    world_width: u32,
+    world_height: u32,
+    width: u32,
+    height: u32,
+    /// Cells marked during the tick before the current one.
+    previous: Vec<Option<DirtyRect>>,
+    /// Cells marked since the previous tick started.
+    current: Vec<Option<DirtyRect>>,
+    /// Cells visited by the last tick, for the debug overlay.
+    active: Vec<Option<DirtyRect>>,
+    /// Whether the world wraps around horizontally and vertically, making cells on opposite
+    /// edges neighbours.
+    wraps: Vector2<bool>,
+    /// Width of the ring of chunks along the edges of the world that never update, in chunks.
+    frozen_margin: u32,
+    /// Chunks whose temperatures may still be changing, see `World::diffuse_heat`.
+    heating: Vec<bool>
+}
+
+impl Chunks {
+    /// Creates the chunks of a world with every cell awake.
+    pub(crate) fn new(world_width: u32, world_height: u32) -> Chunks {
+        let width = world_width.div_ceil(CHUNK_SIZE);
+        let height = world_height.div_ceil(CHUNK_SIZE);
+        let amount_of_chunks = (width * height) as usize;
+
+        let mut chunks = Chunks {
+            world_width,
+            world_height,
+            width,
+            height,
+            previous: vec![None; amount_of_chunks],
+            current: vec![None; amount_of_chunks],
+            active: vec![None; amount_of_chunks],
+            wraps: Vector2::new(false, false),
+            frozen_margin: 0,
+            heating: vec![true; amount_of_chunks]
+        };
+        chunks.wake_all();
+
+        chunks
+    }
+
+    /// Marks every cell as changed, e.g. after the whole world has been replaced.
+    pub(crate) fn wake_all(&mut self) {
+        for chunk_y in 0..self.height {
+            for chunk_x in 0..self.width {
+                let min = Vector2::new(chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE);
+                let max = Vector2::new(
+                    (min.x + CHUNK_SIZE).min(self.world_width) - 1,
+                    (min.y + CHUNK_SIZE).min(self.world_height) - 1
+                );
+
+                self.current[(chunk_x + chunk_y * self.width) as usize] = Some(DirtyRect { min, max });
+            }
+        }
+    }
+
+    /// Stops the outermost `frozen_margin` rings of chunks from updating. Their tiles can still
+    /// be looked at and changed by their neighbours.
+    pub(crate) fn set_frozen_margin(&mut self, frozen_margin: u32) {
+        self.frozen_margin = frozen_margin;
+    }
+
+    pub(crate) fn set_wraps(&mut self, wraps: Vector2<bool>) {
+        self.wraps = wraps;
+    }
+
+    /// Marks the cell at `position` and its neighbours as changed.
+    pub(crate) fn mark(&mut self, position: Vector2<u32>) {
+        let inside = position.x > 0 && position.y > 0 && position.x + 1 < self.world_width && position.y + 1 < self.world_height;
+        if inside {
+            for y in position.y - 1..=position.y + 1 {
+                for x in position.x - 1..=position.x + 1 {
+                    self.mark_cell(Vector2::new(x, y));
+                }
+            }
+            return;
+        }
+
+        // Along the edges some neighbours may be missing or on the opposite edge
+        for y_offset in -1..=1 {
+            let y = match neighbour(position.y, y_offset, self.world_height, self.wraps.y) {
+                Some(y) => y,
+                None => continue
+            };
+
+            for x_offset in -1..=1 {
+                let x = match neighbour(position.x, x_offset, self.world_width, self.wraps.x) {
+                    Some(x) => x,
+                    None => continue
+                };
+
+                self.mark_cell(Vector2::new(x, y));
+            }
+        }
+    }
+
+    fn mark_cell(&mut self, cell: Vector2<u32>) {
+        let chunk_index = self.chunk_index(cell);
+        let dirty_rect = &mut self.current[chunk_index];
+
+        match dirty_rect {
+            Some(dirty_rect) => dirty_rect.include(cell),
+            None => *dirty_rect = Some(DirtyRect::new(cell))
+        }
+    }
+
+    /// Starts a new tick and returns the rectangles of cells it has to visit along with the
+    /// position of their chunk, in chunks.
+    pub(crate) fn begin_tick(&mut self) -> Vec<(Vector2<u32>, DirtyRect)> {
+        let mut rects = Vec::new();
+
+        for chunk_x in 0..self.width {
+            for chunk_y in 0..self.height {
+                let chunk_index = (chunk_x + chunk_y * self.width) as usize;
+
+                let frozen = chunk_x < self.frozen_margin || chunk_y < self.frozen_margin
+                    || chunk_x + self.frozen_margin >= self.width || chunk_y + self.frozen_margin >= self.height;
+                if frozen {
+                    self.active[chunk_index] = None;
+                    continue;
+                }
+
+                let active = match (self.previous[chunk_index], self.current[chunk_index]) {
+                    (Some(previous), Some(current)) => Some(previous.union(current)),
+                    (previous, current) => previous.or(current)
+                };
+
+                self.active[chunk_index] = active;
+                rects.extend(active.map(|active| (Vector2::new(chunk_x, chunk_y), active)));
+            }
+        }
+
+        self.previous = std::mem::replace(&mut self.current, vec![None; self.previous.len()]);
+
+        rects
+    }
+
+    /// Marks the temperatures of the chunk `position` lies in as changing, without waking its tiles.
+    pub(crate) fn mark_heating(&mut self, position: Vector2<u32>) {
+        let chunk_index = self.chunk_index(position);
+        self.heating[chunk_index] = true;
+    }
+
+    /// Returns which chunks need their heat diffused this tick, the ones whose temperatures may
+    /// still be changing and the ones with changed tiles, and starts looking for changes anew.
+    pub(crate) fn begin_heat_tick(&mut self) -> Vec<bool> {
+        let diffusing = (0..self.heating.len())
+            .map(|chunk_index| self.heating[chunk_index] || self.active[chunk_index].is_some() || self.current[chunk_index].is_some())
+            .collect();
+        self.heating.fill(false);
+
+        diffusing
+    }
+
+    /// Number of chunks horizontally and vertically.
+    pub(crate) fn size(&self) -> Vector2<u32> {
+        Vector2::new(self.width, self.height)
+    }
+
+    fn chunk_index(&self, position: Vector2<u32>) -> usize {
+        (position.x / CHUNK_SIZE + position.y / CHUNK_SIZE * self.width) as usize
+    }
+}
+
+/// The coordinate `offset` cells away from `coordinate` on an axis of `size` cells, if there is one.
+fn neighbour(coordinate: u32, offset: i32, size: u32, wraps: bool) -> Option<u32> {
+    let neighbour = coordinate as i32 + offset;
+
+    if (0..size as i32).contains(&neighbour) {
+        Some(neighbour as u32)
+    } else if wraps {
+        Some(neighbour.rem_euclid(size as i32) as u32)
+    } else {
+        None
+    }
+}
+
+const CHUNK_BORDER_COLOR: Color = Color { r: 0, g: 200, b: 80 };
+const DIRTY_RECT_COLOR: Color = Color { r: 255, g: 0, b: 200 };
+
+impl World {
+    /// Marks the cell at `tile_position` so it gets updated in the next two ticks, along with its neighbours.
+    pub fn wake(&mut self, tile_position: Vector2<u32>) {
+        self.chunks.mark(tile_position);
+    }
+
+    /// Number of chunks the last tick updated any cells of.
+    pub fn active_chunk_count(&self) -> usize {
+        self.chunks.active.iter().filter(|active| active.is_some()).count()
+    }
+
+    /// Draws the borders of the chunks the last tick updated and, inside them, the rectangles of
+    /// cells it visited over a frame drawn by `World::render`.
+    pub fn render_chunks(&self, frame: &mut [u8]) {
+        for (chunk_index, active) in self.chunks.active.iter().enumerate() {
+            let dirty_rect = match active {
+                Some(dirty_rect) => dirty_rect,
+                None => continue
+            };
+
+            let chunk_index = chunk_index as u32;
+            let min = Vector2::new(chunk_index % self.chunks.width * CHUNK_SIZE, chunk_index / self.chunks.width * CHUNK_SIZE);
+            let max = Vector2::new((min.x + CHUNK_SIZE).min(self.world_width) - 1, (min.y + CHUNK_SIZE).min(self.world_height) - 1);
+
+            self.draw_rect(frame, DirtyRect { min, max }, CHUNK_BORDER_COLOR);
+            self.draw_rect(frame, *dirty_rect, DIRTY_RECT_COLOR);
+        }
+    }
+
+    fn draw_rect(&self, frame: &mut [u8], rect: DirtyRect, color: Color) {
+        let mut draw = |x: u32, y: u32| {
+            let index = ((x + y * self.world_width) * 4) as usize;
+            frame[index..index + 4].copy_from_slice(&[color.r, color.g, color.b, 255]);
+        };
+
+        for x in rect.min.x..=rect.max.x {
+            draw(x, rect.min.y);
+            draw(x, rect.max.y);
+        }
+        for y in rect.min.y..=rect.max.y {
+            draw(rect.min.x, y);
+            draw(rect.max.x, y);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::world::World;
+
+/// Drives `World::update` at a fixed tick rate, independent of how often it is advanced.
+///
+/// Elapsed wall-clock time is scaled and collected in an accumulator, and one world update
+/// is run for every whole tick in it. At most `max_steps_per_advance` ticks are run per call,
+/// so a long stall doesn't make the simulation spiral trying to catch up.
+#[derive(Copy, Clone, Debug)]
+pub struct SimulationClock {
+    tick_rate: f32,
+    time_scale: f32,
+    max_steps_per_advance: u32,
+    accumulator: f32,
+    paused: bool,
+    pending_steps: u32,
+    current_tick: u64
+}
+
+impl SimulationClock {
+    pub const DEFAULT_TICK_RATE: f32 = 60.0;
+    pub const DEFAULT_MAX_STEPS_PER_ADVANCE: u32 = 5;
+
+    /// Creates a clock running `tick_rate` ticks per second.
+    pub fn new(tick_rate: f32) -> SimulationClock {
+        assert!(tick_rate > 0.0, "tick rate must be positive");
+
+        SimulationClock {
+            tick_rate,
+            time_scale: 1.0,
+            max_steps_per_advance: SimulationClock::DEFAULT_MAX_STEPS_PER_ADVANCE,
+            accumulator: 0.0,
+            paused: false,
+            pending_steps: 0,
+            current_tick: 0
+        }
+    }
+
+    /// Length of a single tick in seconds of simulation time.
+    pub fn tick_duration(&self) -> f32 {
+        1.0 / self.tick_rate
+    }
+
+    pub fn tick_rate(&self) -> f32 {
+        self.tick_rate
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        assert!(tick_rate > 0.0, "tick rate must be positive");
+        self.tick_rate = tick_rate;
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Sets how fast simulation time passes relative to wall-clock time, e.g. `0.5` for half speed.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn max_steps_per_advance(&self) -> u32 {
+        self.max_steps_per_advance
+    }
+
+    pub fn set_max_steps_per_advance(&mut self, max_steps_per_advance: u32) {
+        self.max_steps_per_advance = max_steps_per_advance.max(1);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.accumulator = 0.0;
+    }
+
+    pub fn toggle_paused(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    /// Queues a single tick to run on the next `advance`, even while paused.
+    pub fn step(&mut self) {
+        self.pending_steps += 1;
+    }
+
+    /// Number of ticks run so far. This is the frame number passed to `World::update`.
+    pub fn current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    /// Adds `elapsed` seconds of wall-clock time and runs every tick that is due.
+    ///
+    /// Returns the number of ticks that were run.
+    pub fn advance(&mut self, world: &mut World, elapsed: f32) -> u32 {
+        let tick_duration = self.tick_duration();
+        let mut steps = 0;
+
+        while self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            self.tick(world, tick_duration);
+            steps += 1;
+        }
+
+        if self.paused {
+            return steps;
+        }
+
+        self.accumulator += elapsed.max(0.0) * self.time_scale;
+
+        while self.accumulator >= tick_duration {
+            if steps >= self.max_steps_per_advance {
+                // Drop the time we can't catch up on instead of carrying it into the next frame
+                self.accumulator %= tick_duration;
+                break;
+            }
+
+            self.accumulator -= tick_duration;
+            self.tick(world, tick_duration);
+            steps += 1;
+        }
+
+        steps
+    }
+
+    fn tick(&mut self, world: &mut World, tick_duration: f32) {
+        self.current_tick += 1;
+        world.update(tick_duration, self.current_tick);
+    }
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        SimulationClock::new(SimulationClock::DEFAULT_TICK_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BACKGROUND_COLOR;
+
+    /// A quarter of a second per tick, which adds up exactly in `f32`.
+    const TICK_RATE: f32 = 4.0;
+
+    fn world() -> World {
+        World::with_seed(BACKGROUND_COLOR, 32, 32, 0)
+    }
+
+    #[test]
+    fn accumulates_partial_ticks() {
+        let mut world = world();
+        let mut clock = SimulationClock::new(TICK_RATE);
+
+        assert_eq!(clock.advance(&mut world, 0.125), 0);
+        assert_eq!(clock.advance(&mut world, 0.125), 1);
+        assert_eq!(clock.advance(&mut world, 0.625), 2);
+        assert_eq!(clock.advance(&mut world, 0.125), 1);
+        assert_eq!(clock.current_tick(), 4);
+    }
+
+    #[test]
+    fn caps_catching_up() {
+        let mut world = world();
+        let mut clock = SimulationClock::new(TICK_RATE);
+        clock.set_max_steps_per_advance(3);
+
+        assert_eq!(clock.advance(&mut world, 10.125), 3);
+        // The time beyond the cap is dropped rather than caught up on later
+        assert_eq!(clock.advance(&mut world, 0.125), 1);
+        assert_eq!(clock.advance(&mut world, 0.0), 0);
+        assert_eq!(clock.current_tick(), 4);
+    }
+
+    #[test]
+    fn pausing_stops_and_clears_time() {
+        let mut world = world();
+        let mut clock = SimulationClock::new(TICK_RATE);
+
+        assert_eq!(clock.advance(&mut world, 0.125), 0);
+        clock.set_paused(true);
+        assert_eq!(clock.advance(&mut world, 10.0), 0);
+
+        clock.toggle_paused();
+        assert!(!clock.is_paused());
+        assert_eq!(clock.advance(&mut world, 0.125), 0);
+        assert_eq!(clock.current_tick(), 0);
+    }
+
+    #[test]
+    fn steps_while_paused() {
+        let mut world = world();
+        let mut clock = SimulationClock::new(TICK_RATE);
+        clock.set_paused(true);
+
+        clock.step();
+        clock.step();
+        assert_eq!(clock.advance(&mut world, 0.0), 2);
+        assert_eq!(clock.advance(&mut world, 1.0), 0);
+        assert_eq!(clock.current_tick(), 2);
+    }
+
+    #[test]
+    fn scales_time() {
+        let mut world = world();
+        let mut clock = SimulationClock::new(TICK_RATE);
+
+        clock.set_time_scale(0.5);
+        assert_eq!(clock.advance(&mut world, 1.0), 2);
+
+        clock.set_time_scale(2.0);
+        assert_eq!(clock.advance(&mut world, 0.5), 4);
+
+        clock.set_time_scale(-1.0);
+        assert_eq!(clock.time_scale(), 0.0);
+        assert_eq!(clock.advance(&mut world, 1.0), 0);
+    }
+}
@@ -0,0 +1,108 @@
+//! Changes live tiles ask for outside their own cell.
+//!
+//! While `World::update` walks the grid, tiles queue `TileCommand`s through their `LiveTileApi`
+//! instead of touching other cells directly. The queue is applied in the order it was filled once
+//! every tile has been updated, so a tile never sees a neighbour change halfway through the tick.
+
+use crate::world::World;
+use crate::tile::Tile;
+use crate::particle::Particle;
+use crate::material::MaterialId;
+use cgmath::Vector2;
+use std::collections::HashSet;
+
+/// Something that happened in the world a frontend may want to react to, e.g. with a sound.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// A flammable tile caught fire.
+    Ignited,
+    /// A fire was put out by a liquid.
+    Extinguished,
+    /// A corrosive tile dissolved a neighbour.
+    Dissolved,
+    /// An explosive tile went off.
+    Exploded
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WorldEvent {
+    pub kind: EventKind,
+    pub position: Vector2<u32>
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileCommand {
+    /// Replaces the tile at `position`, provided it still is of material `expected` (`None` for
+    /// empty) when the command is applied.
+    ///
+    /// Only the first command targeting a cell in a tick is applied, later ones are dropped, so
+    /// two tiles can't both claim the same cell.
+    SetTile { position: Vector2<u32>, expected: Option<MaterialId>, tile: Tile },
+    SpawnParticle(Particle),
+    /// Turns the tile at `position` into a particle flying off with `velocity`, on the same
+    /// conditions as `SetTile`.
+    LaunchTile { position: Vector2<u32>, expected: MaterialId, velocity: Vector2<f32> },
+    /// Moves the tile at `from`, provided it still is of `material`, to `to`, provided that still
+    /// is of material `expected`, swapping the two if `switch` is set. Neither cell may have been
+    /// claimed yet. Used for moves across a wrapping edge of the world.
+    MoveTile { from: Vector2<u32>, to: Vector2<u32>, material: MaterialId, expected: Option<MaterialId>, switch: bool },
+    /// Adds heat to the cell at `position`, see `World::add_heat`. Heat from several commands adds up.
+    AddHeat { position: Vector2<u32>, heat: f32 },
+    /// Keeps the cell at `position` updating, see `World::wake`.
+    Wake(Vector2<u32>),
+    Event(WorldEvent)
+}
+
+impl World {
+    /// Events emitted during the last call to `World::update`.
+    pub fn events(&self) -> &[WorldEvent] {
+        &self.events
+    }
+
+    pub(crate) fn apply_commands(&mut self) {
+        let mut commands = std::mem::take(&mut self.commands);
+        let mut claimed_cells = HashSet::new();
+
+        for command in commands.drain(..) {
+            match command {
+                TileCommand::SetTile { position, expected, tile } => {
+                    if self.get_tile(position).get_material() == expected && claimed_cells.insert(position) {
+                        self.set_tile(position, tile);
+                    }
+                }
+                TileCommand::SpawnParticle(particle) => self.add_particle(particle),
+                TileCommand::LaunchTile { position, expected, velocity } => {
+                    let tile = self.get_tile(position);
+
+                    if tile.get_material() == Some(expected) && claimed_cells.insert(position) {
+                        self.set_tile(position, Tile::Empty);
+                        self.add_particle(Particle::new(tile, position.cast().unwrap(), velocity));
+                    }
+                }
+                TileCommand::MoveTile { from, to, material, expected, switch } => {
+                    let tile = self.get_tile(from);
+                    let other_tile = self.get_tile(to);
+
+                    if tile.get_material() == Some(material) && other_tile.get_material() == expected
+                        && !claimed_cells.contains(&from) && !claimed_cells.contains(&to) {
+                        claimed_cells.insert(from);
+                        claimed_cells.insert(to);
+
+                        self.set_tile(to, tile);
+                        self.set_tile(from, if switch { other_tile } else { Tile::Empty });
+
+                        let from_index = (from.x + from.y * self.world_width) as usize;
+                        let to_index = (to.x + to.y * self.world_width) as usize;
+                        self.temperatures.swap(from_index, to_index);
+                    }
+                }
+                TileCommand::AddHeat { position, heat } => self.add_heat(position, heat),
+                TileCommand::Wake(position) => self.wake(position),
+                TileCommand::Event(event) => self.events.push(event)
+            }
+        }
+
+        // Hand the emptied buffer back so its allocation is reused next tick
+        self.commands = commands;
+    }
+}
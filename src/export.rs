@@ -0,0 +1,216 @@
+//! Headless rendering of worlds to PNG sequences and animated GIFs.
+//!
+//! Nothing here needs a window or a GPU, frames are composited on the CPU exactly like
+//! `World::render` does for the pixels surface.
+
+use crate::world::World;
+use image::{RgbaImage, ImageResult, ImageError, Frame, Delay};
+use image::error::{ParameterError, ParameterErrorKind};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::{self, FilterType};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+impl World {
+    /// Renders the tiles and particles into a new image.
+    pub fn render_image(&self) -> RgbaImage {
+        let mut frame = vec![0; self.frame.len()];
+        self.render(&mut frame);
+
+        RgbaImage::from_raw(self.world_width, self.world_height, frame)
+            .expect("frame size matches the world size")
+    }
+
+    /// Renders the world and upscales it by an integer factor, keeping tiles as sharp squares.
+    pub fn render_image_scaled(&self, scale: u32) -> RgbaImage {
+        let image = self.render_image();
+
+        if scale <= 1 {
+            return image;
+        }
+
+        imageops::resize(&image, image.width() * scale, image.height() * scale, FilterType::Nearest)
+    }
+}
+
+/// Writes a numbered PNG file to a directory every `every_n_ticks` ticks.
+pub struct PngSequenceExporter {
+    directory: PathBuf,
+    every_n_ticks: u64,
+    scale: u32,
+    frames_written: u32
+}
+
+impl PngSequenceExporter {
+    /// Creates the exporter, creating `directory` if it doesn't exist yet.
+    pub fn new<P: Into<PathBuf>>(directory: P, every_n_ticks: u64) -> io::Result<PngSequenceExporter> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        Ok(PngSequenceExporter {
+            directory,
+            every_n_ticks: every_n_ticks.max(1),
+            scale: 1,
+            frames_written: 0
+        })
+    }
+
+    pub fn with_scale(mut self, scale: u32) -> PngSequenceExporter {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn frames_written(&self) -> u32 {
+        self.frames_written
+    }
+
+    /// Writes a frame if `tick` is one of the ticks to export. Returns whether a frame was written.
+    pub fn capture(&mut self, world: &World, tick: u64) -> ImageResult<bool> {
+        if !tick.is_multiple_of(self.every_n_ticks) {
+            return Ok(false);
+        }
+
+        self.write_frame(world)?;
+
+        Ok(true)
+    }
+
+    /// Writes the current state of the world as the next frame and returns its path.
+    pub fn write_frame(&mut self, world: &World) -> ImageResult<PathBuf> {
+        let path = self.directory.join(format!("frame_{:06}.png", self.frames_written));
+        world.render_image_scaled(self.scale).save(&path)?;
+        self.frames_written += 1;
+
+        Ok(path)
+    }
+}
+
+/// Encodes a looping animated GIF from a frame every `every_n_ticks` ticks.
+///
+/// Call `GifRecorder::finish` once done, dropping the recorder finishes the GIF as well but
+/// ignores any errors.
+pub struct GifRecorder<W: Write> {
+    encoder: GifEncoder<SharedWriter<W>>,
+    writer: SharedWriter<W>,
+    every_n_ticks: u64,
+    scale: u32,
+    frame_delay: Delay,
+    frames_written: u32
+}
+
+impl<W: Write> GifRecorder<W> {
+    /// `tick_rate` is the number of ticks per second the simulation runs at, used to play the GIF back in real time.
+    /// It has to be positive and finite.
+    pub fn new(writer: W, every_n_ticks: u64, tick_rate: f32) -> ImageResult<GifRecorder<W>> {
+        if !(tick_rate > 0.0 && tick_rate.is_finite()) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!("tick rate must be positive, not {}", tick_rate))
+            )));
+        }
+
+        let every_n_ticks = every_n_ticks.max(1);
+
+        // Quantizing every frame is the slow part of encoding, trade some color accuracy for speed
+        let writer = SharedWriter(Arc::new(Mutex::new((writer, None))));
+        let mut encoder = GifEncoder::new_with_speed(writer.clone(), 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        Ok(GifRecorder {
+            encoder,
+            writer,
+            every_n_ticks,
+            scale: 1,
+            frame_delay: Delay::from_saturating_duration(Duration::from_secs_f32(every_n_ticks as f32 / tick_rate)),
+            frames_written: 0
+        })
+    }
+
+    pub fn with_scale(mut self, scale: u32) -> GifRecorder<W> {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn frames_written(&self) -> u32 {
+        self.frames_written
+    }
+
+    /// Adds a frame if `tick` is one of the ticks to record. Returns whether a frame was added.
+    pub fn capture(&mut self, world: &World, tick: u64) -> ImageResult<bool> {
+        if !tick.is_multiple_of(self.every_n_ticks) {
+            return Ok(false);
+        }
+
+        self.write_frame(world)?;
+
+        Ok(true)
+    }
+
+    /// Adds the current state of the world as the next frame.
+    pub fn write_frame(&mut self, world: &World) -> ImageResult<()> {
+        let image = world.render_image_scaled(self.scale);
+        self.encoder.encode_frame(Frame::from_parts(image, 0, 0, self.frame_delay))?;
+        self.frames_written += 1;
+
+        Ok(())
+    }
+
+    /// Writes the end of the GIF and flushes it.
+    pub fn finish(self) -> ImageResult<()> {
+        // The encoder writes the trailer when dropped, any error ends up with the shared writer
+        drop(self.encoder);
+
+        let (mut writer, error) = Arc::try_unwrap(self.writer.0)
+            .unwrap_or_else(|_| unreachable!("the encoder was the only other owner"))
+            .into_inner()
+            .unwrap_or_else(|error| error.into_inner());
+        if let Some(error) = error {
+            return Err(error.into());
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// The writer a `GifRecorder` hands its encoder. It keeps the first error, since the encoder
+/// can't report the one from writing the trailer.
+struct SharedWriter<W>(Arc<Mutex<(W, Option<io::Error>)>>);
+
+impl<W> Clone for SharedWriter<W> {
+    fn clone(&self) -> SharedWriter<W> {
+        SharedWriter(self.0.clone())
+    }
+}
+
+impl<W: Write> SharedWriter<W> {
+    fn keep_error<T>(&self, write: impl FnOnce(&mut W) -> io::Result<T>) -> io::Result<T> {
+        let mut state = self.0.lock().unwrap_or_else(|error| error.into_inner());
+        let (writer, first_error) = &mut *state;
+
+        write(writer).inspect_err(|error| {
+            first_error.get_or_insert_with(|| io::Error::new(error.kind(), error.to_string()));
+        })
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.keep_error(|writer| writer.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.keep_error(|writer| writer.flush())
+    }
+}
+
+impl GifRecorder<BufWriter<File>> {
+    /// Creates a recorder writing to a new file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P, every_n_ticks: u64, tick_rate: f32) -> ImageResult<GifRecorder<BufWriter<File>>> {
+        let file = File::create(path)?;
+
+        GifRecorder::new(BufWriter::new(file), every_n_ticks, tick_rate)
+    }
+}
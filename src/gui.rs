@@ -1,7 +1,32 @@
 ﻿use pixels::{raw_window_handle::HasRawWindowHandle, wgpu, PixelsContext};
 use std::time::Instant;
 use imgui::*;
-use crate::SelectedMaterial;
+use powpowder::{MaterialId, MaterialRegistry};
+use powpowder::boundary::{Boundaries, BoundaryMode};
+use powpowder::resize::Anchor;
+use std::sync::Arc;
+
+/// Actions requested through the GUI that have to be carried out by the main loop.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GuiCommand {
+    SaveWorld,
+    OpenWorld,
+    ImportImage,
+    SetBoundaries(Boundaries),
+    ResizeWorld { width: u32, height: u32, anchor: Anchor },
+    NewWorld { width: u32, height: u32 }
+}
+
+/// Largest world width or height the size fields accept, the largest a saved world can have.
+const MAX_WORLD_SIZE: i32 = powpowder::save::MAX_WORLD_SIZE as i32;
+
+const ANCHORS: [Anchor; 9] = [
+    Anchor::TopLeft, Anchor::Top, Anchor::TopRight,
+    Anchor::Left, Anchor::Center, Anchor::Right,
+    Anchor::BottomLeft, Anchor::Bottom, Anchor::BottomRight
+];
+
+const BOUNDARY_MODES: [BoundaryMode; 4] = [BoundaryMode::Wall, BoundaryMode::Void, BoundaryMode::Wrap, BoundaryMode::Mirror];
 
 /// Manages all state required for rendering Dear ImGui over `Pixels`.
 pub(crate) struct Gui {
@@ -11,7 +36,17 @@ pub(crate) struct Gui {
 
     last_frame: Instant,
     last_cursor: Option<imgui::MouseCursor>,
-    selected_material: SelectedMaterial,
+    materials: Arc<MaterialRegistry>,
+    material_names: Vec<(MaterialId, ImString)>,
+    selected_material: MaterialId,
+    show_temperature: bool,
+    show_chunks: bool,
+    boundaries: Boundaries,
+    world_size: [i32; 2],
+    anchor: Anchor,
+    world_path: ImString,
+    status: Option<String>,
+    pending_command: Option<GuiCommand>,
 }
 
 impl Gui {
@@ -19,6 +54,7 @@ impl Gui {
     pub(crate) fn new<W: HasRawWindowHandle>(
         window: &winit::window::Window,
         pixels: &pixels::Pixels<W>,
+        materials: Arc<MaterialRegistry>,
     ) -> Self {
         // Create Dear ImGui context
         let mut imgui = imgui::Context::create();
@@ -28,7 +64,7 @@ impl Gui {
         let mut platform = imgui_winit_support::WinitPlatform::init(&mut imgui);
         platform.attach_window(
             imgui.io_mut(),
-            &window,
+            window,
             imgui_winit_support::HiDpiMode::Default,
         );
 
@@ -57,7 +93,11 @@ impl Gui {
         let device = pixels.device();
         let queue = pixels.queue();
         let texture_format = wgpu::TextureFormat::Bgra8UnormSrgb;
-        let renderer = imgui_wgpu::Renderer::new(&mut imgui, &device, &queue, texture_format);
+        let renderer = imgui_wgpu::Renderer::new(&mut imgui, device, queue, texture_format);
+
+        let material_names = materials.iter()
+            .map(|(material_id, material)| (material_id, ImString::new(&material.name)))
+            .collect();
 
         // Return GUI context
         Self {
@@ -67,7 +107,17 @@ impl Gui {
 
             last_frame: Instant::now(),
             last_cursor: None,
-            selected_material: SelectedMaterial::Stone
+            materials,
+            material_names,
+            selected_material: MaterialId(0),
+            show_temperature: false,
+            show_chunks: false,
+            boundaries: Boundaries::default(),
+            world_size: [400, 300],
+            anchor: Anchor::default(),
+            world_path: ImString::new("world.powpowder"),
+            status: None,
+            pending_command: None
         }
     }
 
@@ -104,9 +154,76 @@ impl Gui {
         // ui.begin_group().end(&ui);
         
         // ui.show_demo_window(&mut true);
-        ui.radio_button(im_str!("Stone"), &mut self.selected_material, SelectedMaterial::Stone);
-        ui.radio_button(im_str!("Sand"), &mut self.selected_material, SelectedMaterial::Sand);
-        ui.radio_button(im_str!("Water"), &mut self.selected_material, SelectedMaterial::Water);
+        for (material_id, material_name) in &self.material_names {
+            ui.radio_button(material_name, &mut self.selected_material, *material_id);
+        }
+
+        ui.separator();
+        ui.checkbox(im_str!("Show temperature"), &mut self.show_temperature);
+        ui.checkbox(im_str!("Show active chunks"), &mut self.show_chunks);
+
+        ui.separator();
+        let boundary_names = [im_str!("Wall"), im_str!("Void"), im_str!("Wrap"), im_str!("Mirror")];
+        let edges = [
+            (im_str!("Left edge"), &mut self.boundaries.left),
+            (im_str!("Right edge"), &mut self.boundaries.right),
+            (im_str!("Top edge"), &mut self.boundaries.top),
+            (im_str!("Bottom edge"), &mut self.boundaries.bottom)
+        ];
+        let mut boundaries_changed = false;
+        for (label, mode) in edges {
+            let mut index = BOUNDARY_MODES.iter().position(|boundary_mode| boundary_mode == mode).unwrap_or(0);
+            if ComboBox::new(label).build_simple_string(&ui, &mut index, &boundary_names) {
+                *mode = BOUNDARY_MODES[index];
+                boundaries_changed = true;
+            }
+        }
+        if boundaries_changed {
+            self.pending_command = Some(GuiCommand::SetBoundaries(self.boundaries));
+        }
+
+        ui.separator();
+        ui.input_int2(im_str!("Size"), &mut self.world_size).build();
+        for size in &mut self.world_size {
+            *size = (*size).clamp(1, MAX_WORLD_SIZE);
+        }
+        let anchor_names = [
+            im_str!("Top left"), im_str!("Top"), im_str!("Top right"),
+            im_str!("Left"), im_str!("Center"), im_str!("Right"),
+            im_str!("Bottom left"), im_str!("Bottom"), im_str!("Bottom right")
+        ];
+        let current_anchor = self.anchor;
+        let mut anchor_index = ANCHORS.iter().position(|&anchor| anchor == current_anchor).unwrap_or(0);
+        if ComboBox::new(im_str!("Anchor")).build_simple_string(&ui, &mut anchor_index, &anchor_names) {
+            self.anchor = ANCHORS[anchor_index];
+        }
+        let [width, height] = [self.world_size[0] as u32, self.world_size[1] as u32];
+        if ui.button(im_str!("Resize"), [0.0, 0.0]) {
+            self.pending_command = Some(GuiCommand::ResizeWorld { width, height, anchor: self.anchor });
+        }
+        ui.same_line(0.0);
+        if ui.button(im_str!("New world"), [0.0, 0.0]) {
+            self.pending_command = Some(GuiCommand::NewWorld { width, height });
+        }
+
+        ui.separator();
+        ui.input_text(im_str!("File"), &mut self.world_path)
+            .resize_buffer(true)
+            .build();
+        if ui.button(im_str!("Save"), [0.0, 0.0]) {
+            self.pending_command = Some(GuiCommand::SaveWorld);
+        }
+        ui.same_line(0.0);
+        if ui.button(im_str!("Open"), [0.0, 0.0]) {
+            self.pending_command = Some(GuiCommand::OpenWorld);
+        }
+        ui.same_line(0.0);
+        if ui.button(im_str!("Import image"), [0.0, 0.0]) {
+            self.pending_command = Some(GuiCommand::ImportImage);
+        }
+        if let Some(status) = &self.status {
+            ui.text(status);
+        }
         
         // let w = Window::new(im_str!("Aaaa"))
         //     .position([20.0, 20.0], Condition::Appearing)
@@ -133,8 +250,53 @@ impl Gui {
             .render(ui.render(), &context.queue, &context.device, &mut rpass)
     }
     
-    pub fn get_selected_material(&self) -> SelectedMaterial {
-        self.selected_material
+    /// The material picked in the palette, `None` if the registry has no materials.
+    pub fn get_selected_material(&self) -> Option<MaterialId> {
+        if self.materials.is_empty() {
+            None
+        } else {
+            Some(self.selected_material)
+        }
+    }
+
+    /// Whether the world should be drawn with the temperature overlay.
+    pub fn get_show_temperature(&self) -> bool {
+        self.show_temperature
+    }
+
+    /// Whether the chunks updated by the last tick should be outlined.
+    pub fn get_show_chunks(&self) -> bool {
+        self.show_chunks
+    }
+
+    /// Shows `boundaries` as the current ones, e.g. after opening a world.
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.boundaries = boundaries;
+    }
+
+    /// Fills in the size fields, e.g. with the size of a world that was just opened.
+    pub fn set_world_size(&mut self, width: u32, height: u32) {
+        self.world_size = [width as i32, height as i32];
+    }
+
+    /// Recreates the renderer for a new `Pixels`, whose device the old one doesn't belong to.
+    pub(crate) fn recreate_renderer<W: HasRawWindowHandle>(&mut self, pixels: &pixels::Pixels<W>) {
+        let texture_format = wgpu::TextureFormat::Bgra8UnormSrgb;
+        self.renderer = imgui_wgpu::Renderer::new(&mut self.imgui, pixels.device(), pixels.queue(), texture_format);
+    }
+
+    pub fn get_world_path(&self) -> &str {
+        self.world_path.to_str()
+    }
+
+    /// Takes the command requested since the last call, if any.
+    pub fn take_command(&mut self) -> Option<GuiCommand> {
+        self.pending_command.take()
+    }
+
+    /// Shows a one-line status message, e.g. the result of the last save.
+    pub fn set_status(&mut self, status: String) {
+        self.status = Some(status);
     }
 }
 
@@ -0,0 +1,166 @@
+//! Building world layouts from images.
+//!
+//! Every pixel is matched against a `Palette` of colors and turned into the material of the
+//! closest entry, so scenes can be drawn in any paint program.
+
+use crate::{Color, BACKGROUND_COLOR};
+use crate::world::{World, WorldRng};
+use crate::tile::Tile;
+use crate::material::{MaterialId, MaterialRegistry};
+use crate::temperature::AMBIENT_TEMPERATURE;
+use cgmath::Vector2;
+use image::{RgbaImage, ImageResult};
+use image::imageops::{self, FilterType};
+use std::path::Path;
+
+/// What a palette color turns into.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PaletteMaterial {
+    Empty,
+    /// A tile of the material. Static tiles keep the color of the pixel they came from, others
+    /// get a color from the material's gradient.
+    Material(MaterialId)
+}
+
+impl PaletteMaterial {
+    fn create_tile(self, pixel_color: Color, materials: &MaterialRegistry, rng: &mut WorldRng) -> Tile {
+        match self {
+            PaletteMaterial::Empty => Tile::Empty,
+            PaletteMaterial::Material(material) => match materials.create_tile(material, rng) {
+                Tile::StaticTile(mut static_tile) => {
+                    static_tile.color = pixel_color;
+                    Tile::StaticTile(static_tile)
+                }
+                tile => tile
+            }
+        }
+    }
+}
+
+/// Maps pixel colors to materials.
+///
+/// A pixel becomes the material of the nearest palette color, unless it is further away than
+/// the palette's tolerance, in which case it becomes the fallback material. Mostly transparent
+/// pixels are always empty.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    entries: Vec<(Color, PaletteMaterial)>,
+    tolerance: u32,
+    fallback: PaletteMaterial
+}
+
+impl Palette {
+    pub const DEFAULT_TOLERANCE: u32 = 96;
+
+    /// Creates a palette without any colors; every pixel becomes `fallback`.
+    pub fn new(fallback: PaletteMaterial) -> Palette {
+        Palette {
+            entries: Vec::new(),
+            tolerance: Palette::DEFAULT_TOLERANCE,
+            fallback
+        }
+    }
+
+    pub fn with_color(mut self, color: Color, material: PaletteMaterial) -> Palette {
+        self.entries.push((color, material));
+        self
+    }
+
+    /// Sets the largest distance in RGB space a pixel can be from a palette color and still match it.
+    pub fn with_tolerance(mut self, tolerance: u32) -> Palette {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn material_for(&self, pixel_color: Color) -> PaletteMaterial {
+        let distance_squared = |color: Color| {
+            let dr = color.r as i32 - pixel_color.r as i32;
+            let dg = color.g as i32 - pixel_color.g as i32;
+            let db = color.b as i32 - pixel_color.b as i32;
+
+            (dr * dr + dg * dg + db * db) as u32
+        };
+
+        self.entries.iter()
+            .map(|&(color, material)| (distance_squared(color), material))
+            .filter(|&(distance_squared, _)| distance_squared <= self.tolerance * self.tolerance)
+            .min_by_key(|&(distance_squared, _)| distance_squared)
+            .map(|(_, material)| material)
+            .unwrap_or(self.fallback)
+    }
+
+    /// Dark grey is stone, yellow is sand, blue is water and white or the background color is empty.
+    ///
+    /// Colors of materials that `materials` doesn't define are left out.
+    pub fn standard(materials: &MaterialRegistry) -> Palette {
+        let mut palette = Palette::new(PaletteMaterial::Empty)
+            .with_color(Color::new(255, 255, 255), PaletteMaterial::Empty)
+            .with_color(BACKGROUND_COLOR, PaletteMaterial::Empty);
+
+        let material_colors = [
+            ("stone", Color::new(64, 64, 64)),
+            ("sand", Color::new(255, 220, 0)),
+            ("water", Color::new(0, 0, 255))
+        ];
+
+        for &(id, color) in &material_colors {
+            if let Some(material) = materials.find(id) {
+                palette = palette.with_color(color, PaletteMaterial::Material(material));
+            }
+        }
+
+        palette
+    }
+}
+
+/// How an image that doesn't match the world size is fitted to it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFit {
+    /// Scale the image to the world size with nearest neighbour sampling, so palette colors stay intact.
+    Resize,
+    /// Keep the image at its original scale, anchored to the top left. Pixels outside the world
+    /// are dropped and tiles outside the image are left empty.
+    Crop
+}
+
+impl World {
+    /// Replaces every tile in the world with the layout in `image`. Existing particles are removed
+    /// and every cell starts at the ambient temperature, or its material's initial temperature.
+    pub fn import_image(&mut self, image: &RgbaImage, palette: &Palette, fit: ImageFit) {
+        let resized;
+        let image = match fit {
+            ImageFit::Resize if image.dimensions() != (self.world_width, self.world_height) => {
+                resized = imageops::resize(image, self.world_width, self.world_height, FilterType::Nearest);
+                &resized
+            }
+            _ => image
+        };
+
+        self.particles.clear();
+        self.temperatures.fill(AMBIENT_TEMPERATURE);
+
+        for x in 0..self.world_width {
+            for y in 0..self.world_height {
+                let pixel = if x < image.width() && y < image.height() { Some(image.get_pixel(x, y)) } else { None };
+
+                let tile = match pixel {
+                    Some(pixel) if pixel[3] >= 128 => {
+                        let pixel_color = Color::new(pixel[0], pixel[1], pixel[2]);
+                        palette.material_for(pixel_color).create_tile(pixel_color, &self.materials, &mut self.rng)
+                    }
+                    _ => Tile::Empty
+                };
+
+                self.place_tile(Vector2::new(x, y), tile);
+            }
+        }
+    }
+
+    /// Loads a PNG or GIF file and imports it with `World::import_image`.
+    pub fn import_image_file<P: AsRef<Path>>(&mut self, path: P, palette: &Palette, fit: ImageFit) -> ImageResult<()> {
+        let image = image::open(path)?.into_rgba8();
+        self.import_image(&image, palette, fit);
+
+        Ok(())
+    }
+}
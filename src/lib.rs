@@ -0,0 +1,69 @@
+//! Falling sand simulation core, usable without a window.
+//!
+//! The windowed frontend in `main.rs` is only built with the `gui` feature (enabled by default).
+
+use palette::rgb::Rgb;
+
+pub mod world;
+pub mod live_tiles;
+pub mod tile;
+pub mod particle;
+pub mod material;
+pub mod brush;
+pub mod temperature;
+pub mod reaction;
+pub mod command;
+pub mod chunk;
+pub mod schedule;
+pub mod boundary;
+pub mod resize;
+pub mod streaming;
+pub mod camera;
+pub mod clock;
+pub mod save;
+pub mod import;
+pub mod export;
+
+pub use crate::world::World;
+pub use crate::tile::{Tile, StaticTile, LiveTile};
+pub use crate::material::{MaterialId, MaterialRegistry};
+pub use crate::particle::Particle;
+pub use crate::clock::SimulationClock;
+pub use crate::save::SaveError;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8
+}
+
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            r,
+            g,
+            b
+        }
+    }
+}
+
+impl From<Rgb> for Color {
+    fn from(rgb_color: Rgb) -> Color {
+        let (r, g, b) = rgb_color.into();
+        let r: u8 = (r * 255.0) as u8;
+        let g: u8 = (g * 255.0) as u8;
+        let b: u8 = (b * 255.0) as u8;
+
+        Color::new(r, g, b)
+    }
+}
+
+impl From<(u8, u8, u8)> for Color {
+    fn from(rgb_color: (u8, u8, u8)) -> Color {
+        let (r, g, b) = rgb_color;
+        Color::new(r, g, b)
+    }
+}
+
+pub const BACKGROUND_COLOR: Color = Color { r: 234, g: 231, b: 217 };
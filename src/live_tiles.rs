@@ -1,113 +1,333 @@
-﻿use palette::rgb::Rgb;
-use crate::{Color};
-use palette::{Lch, Gradient};
-use rand::{thread_rng, Rng, random};
-use lazy_static::lazy_static;
-use crate::tile::{Tile, LiveTile, LiveTileState, LiveTileApi, LiveTileInstruction};
+﻿use rand::Rng;
+use crate::material::{Behavior, Material, Explosion};
+use crate::tile::{Tile, LiveTile, LiveTileApi, LiveTileInstruction};
+use crate::command::EventKind;
 use cgmath::Vector2;
-use palette::encoding::Srgb;
 
-lazy_static! {
-    static ref SAND_GRADIENT: Gradient<Lch> = Gradient::new(vec![
-        Lch::new(78.0, 25.0, 92.0),
-        Lch::new(83.0, 25.0, 92.0)
-    ]);
-    
-    static ref WATER_GRADIENT: Gradient<Lch> = Gradient::new(vec![
-        Lch::new(65.0, 37.0, 249.0),
-        Lch::new(70.0, 37.0, 249.0)
-    ]);
+/// The eight surrounding tiles, orthogonal ones first.
+const NEIGHBOURS: [Vector2<i32>; 8] = [
+    Vector2::new(0, -1), Vector2::new(-1, 0), Vector2::new(1, 0), Vector2::new(0, 1),
+    Vector2::new(-1, -1), Vector2::new(1, -1), Vector2::new(-1, 1), Vector2::new(1, 1)
+];
+
+/// The material at `tile_offset` if it is a liquid or a gas, which other tiles can swap places with.
+fn get_fluid<'a>(api: &LiveTileApi<'a>, tile_offset: Vector2<i32>) -> Option<&'a Material> {
+    match api.get(tile_offset) {
+        Tile::LiveTile(_) => api.get_material(tile_offset)
+            .filter(|material| material.behavior == Behavior::Liquid || material.behavior == Behavior::Gas),
+        _ => None
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub struct SandTile {
-    pub color: Color,
-    pub under_water_ticks: u16
+/// Moves `tile` towards the phase transition its temperature is past, if any.
+///
+/// Past a transition's threshold the tile's temperature is held at the threshold and the heat
+/// beyond it is stored in the tile instead, until the tile has taken in (or given off) the
+/// transition's latent heat and turns into the new material. Stored heat is given back once the
+/// temperature has moved away from every threshold again.
+pub fn update_phase(tile: &mut LiveTile, api: &mut LiveTileApi) -> Option<LiveTileInstruction> {
+    let here = Vector2::new(0, 0);
+    let material = api.materials.get(tile.material);
+    let temperature = api.get_temperature(here);
+
+    let transition = match material.transitions.iter().find(|transition| transition.when.is_passed(temperature)) {
+        Some(transition) => transition,
+        None => {
+            let at_threshold = material.transitions.iter().any(|transition| transition.when.temperature() == temperature);
+            if tile.absorbed_heat != 0.0 && !at_threshold {
+                api.set_temperature(here, temperature + tile.absorbed_heat / material.heat_capacity);
+                tile.absorbed_heat = 0.0;
+            }
+
+            return None;
+        }
+    };
+
+    let threshold = transition.when.temperature();
+    tile.absorbed_heat += (temperature - threshold) * material.heat_capacity;
+    api.set_temperature(here, threshold);
+
+    let latent_heat = transition.latent_heat.copysign(tile.absorbed_heat);
+    if tile.absorbed_heat.abs() < transition.latent_heat {
+        return None;
+    }
+
+    // Heat beyond the latent heat carries over into the new tile
+    let new_material = api.materials.get(transition.into);
+    api.set_temperature(here, threshold + (tile.absorbed_heat - latent_heat) / new_material.heat_capacity);
+
+    Some(LiveTileInstruction::ReplaceSelfWith(api.materials.create_tile(transition.into, api.rng)))
 }
 
-impl SandTile {
-    pub fn new() -> Self {
-        let color: Lch = SAND_GRADIENT.get(thread_rng().gen_range(0.0, 1.0));
-        let color: Rgb = color.into();
-        
-        SandTile {
-            color: color.into(),
-            under_water_ticks: 0
+/// Sets `tile` alight when it is flammable and either a neighbour is burning or it's hot enough
+/// to catch fire by itself. Explosive tiles explode instead.
+pub fn update_combustion(tile: &mut LiveTile, api: &mut LiveTileApi) -> Option<LiveTileInstruction> {
+    let material = api.materials.get(tile.material);
+    let flammability = material.flammability?;
+
+    let mut ignites = flammability.ignition_temperature
+        .is_some_and(|ignition_temperature| api.get_temperature(Vector2::new(0, 0)) >= ignition_temperature);
+    let mut next_to_fire = false;
+
+    for &offset in &NEIGHBOURS {
+        if ignites {
+            break;
         }
+
+        let is_burning = api.get_material(offset).map(|material| material.behavior) == Some(Behavior::Fire);
+        next_to_fire |= is_burning;
+        ignites = is_burning && api.rng.gen::<f32>() < flammability.ignition_chance;
     }
 
-    pub fn update(&mut self, api: LiveTileApi) -> LiveTileInstruction {
-        let tile_below = api.get(Vector2::new(0, 1));
-        
-        if tile_below == Tile::Empty {
-            return LiveTileInstruction::Replace(Vector2::new(0, 1));
-        } else {
-            let random_direction = if random() { -1 } else { 1 };
-            if api.get(Vector2::new(random_direction, 1)) == Tile::Empty {
-                return LiveTileInstruction::Replace(Vector2::new(random_direction, 1));
-            }
+    if !ignites {
+        if next_to_fire {
+            api.keep_awake();
         }
-        
-        if let Tile::LiveTile(LiveTile { state: LiveTileState::Water(_), last_frame_updated: _ }) = api.get(Vector2::new(0, 1)) {
-            if self.under_water_ticks > 3 {
-                self.under_water_ticks = 0;
-                return LiveTileInstruction::Switch(Vector2::new(0, 1));
-            } else {
-                self.under_water_ticks += 1;
+
+        return None;
+    }
+
+    if let Some(explosion) = material.explosion {
+        return Some(explode(explosion, api));
+    }
+
+    api.emit_event(EventKind::Ignited);
+
+    let fire = match api.materials.create_tile(flammability.burns_as, api.rng) {
+        Tile::LiveTile(fire) => Tile::LiveTile(LiveTile { ticks_left: flammability.burn_duration, ..fire }),
+        fire => fire
+    };
+
+    Some(LiveTileInstruction::ReplaceSelfWith(fire))
+}
+
+/// Blasts the tiles within the explosion's radius away from the tile.
+///
+/// The blast weakens with distance and by the blast resistance of what it hits. Tiles close to
+/// the middle are destroyed, the ones further out are thrown out as particles, and every cell in
+/// the radius heats up. Other explosives are left in place, the heat sets them off in turn.
+fn explode(explosion: Explosion, api: &mut LiveTileApi) -> LiveTileInstruction {
+    let radius = explosion.radius as i32;
+
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            let offset = Vector2::new(x, y);
+            let distance = ((x * x + y * y) as f32).sqrt();
+            if offset == Vector2::new(0, 0) || distance > radius as f32 {
+                continue;
             }
-        } else {
-            let random_direction = if random() { -1 } else { 1 };
-            
-            if let Tile::LiveTile(LiveTile { state: LiveTileState::Water(_), last_frame_updated: _ }) = api.get(Vector2::new(random_direction, 1)) {
-                if self.under_water_ticks > 3 {
-                    self.under_water_ticks = 0;
-                    return LiveTileInstruction::Switch(Vector2::new(random_direction, 1));
-                } else {
-                    self.under_water_ticks += 1;
-                }
+
+            // Full strength next to the explosion, dropping off towards the edge
+            let falloff = 1.0 - (distance - 1.0) / radius as f32;
+            api.add_heat(offset, explosion.heat * falloff);
+
+            let material = match api.get_material(offset) {
+                Some(material) => material,
+                None => continue
+            };
+            if material.explosion.is_some() {
+                continue;
+            }
+
+            let blast = falloff * api.rng.gen_range(0.5, 1.0) - material.blast_resistance;
+            if blast <= 0.0 {
+                continue;
+            }
+
+            if blast > 0.6 {
+                api.set_tile(offset, Tile::Empty);
+            } else {
+                // Throw tiles outwards and a little upwards, so they arc back down
+                let direction = Vector2::new(x as f32, y as f32 - distance * 0.5) / distance;
+                api.launch_tile(offset, direction * explosion.force * blast);
             }
         }
+    }
+
+    api.emit_event(EventKind::Exploded);
+
+    let remains = match explosion.leaves {
+        Some(leaves) => api.materials.create_tile(leaves, api.rng),
+        None => Tile::Empty
+    };
+
+    LiveTileInstruction::ReplaceSelfWith(remains)
+}
+
+/// Counts down the tile's `ticks_left` and replaces it with what it decays into once they run out.
+pub fn update_lifetime(tile: &mut LiveTile, api: &mut LiveTileApi) -> Option<LiveTileInstruction> {
+    let material = api.materials.get(tile.material);
+
+    // Fires get their lifetime from what's burning, even when their material doesn't have one
+    if material.lifetime.is_none() && material.behavior != Behavior::Fire {
+        return None;
+    }
+
+    if tile.ticks_left > 0 {
+        tile.ticks_left -= 1;
+        return None;
+    }
+
+    let replacement = match material.decays_into {
+        Some(decays_into) => api.materials.create_tile(decays_into, api.rng),
+        None => Tile::Empty
+    };
 
-        LiveTileInstruction::None
+    Some(LiveTileInstruction::ReplaceSelfWith(replacement))
+}
+
+/// Lets a corrosive tile dissolve a random orthogonal neighbour, which costs it one point of
+/// strength. Once its strength is used up the tile disappears.
+pub fn update_corrosion(tile: &mut LiveTile, api: &mut LiveTileApi) -> Option<LiveTileInstruction> {
+    let corrosion = api.materials.get(tile.material).corrosion?;
+
+    if tile.strength == 0 {
+        return Some(LiveTileInstruction::ReplaceSelfWith(Tile::Empty));
+    }
+
+    // Corrosive materials don't eat each other
+    let can_dissolve = |material: Option<&Material>| material.is_some_and(|material| material.corrosion.is_none() && material.acid_resistance < 1.0);
+
+    if NEIGHBOURS[..4].iter().any(|&offset| can_dissolve(api.get_material(offset))) {
+        api.keep_awake();
     }
+
+    let offset = NEIGHBOURS[api.rng.gen_range(0, 4)];
+    let neighbour = api.get_material(offset)?;
+
+    if !can_dissolve(Some(neighbour)) || api.rng.gen::<f32>() >= corrosion.chance * (1.0 - neighbour.acid_resistance) {
+        return None;
+    }
+
+    tile.strength -= 1;
+    api.set_tile(offset, Tile::Empty);
+    api.emit_event(EventKind::Dissolved);
+
+    // Eating through something keeps the tile busy for the tick
+    Some(LiveTileInstruction::None)
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub struct WaterTile {
-    pub color: Color,
-    frames_since_color_change: u16
+pub fn update_fire(tile: &mut LiveTile, mut api: LiveTileApi) -> LiveTileInstruction {
+    let is_quenched = NEIGHBOURS[..4].iter().any(|&offset| match api.get_material(offset) {
+        Some(material) => material.behavior == Behavior::Liquid && material.flammability.is_none(),
+        None => false
+    });
+
+    if !is_quenched {
+        return LiveTileInstruction::None;
+    }
+
+    api.emit_event(EventKind::Extinguished);
+
+    let replacement = match api.materials.get(tile.material).quenched_into {
+        Some(quenched_into) => api.materials.create_tile(quenched_into, api.rng),
+        None => Tile::Empty
+    };
+
+    LiveTileInstruction::ReplaceSelfWith(replacement)
 }
 
-impl WaterTile {
-    pub fn new() -> WaterTile {
-        let color: Lch = WATER_GRADIENT.get(thread_rng().gen_range(0.0, 1.0));
-        let color: Rgb = color.into();
+/// Lets a tile sink into a lighter liquid or gas directly or diagonally below it, with its
+/// material's `sink_chance` per tick.
+fn sink(tile: &LiveTile, api: &mut LiveTileApi) -> Option<LiveTileInstruction> {
+    let material = api.materials.get(tile.material);
+    let random_direction = if api.rng.gen() { -1 } else { 1 };
+
+    for &offset in &[Vector2::new(0, 1), Vector2::new(random_direction, 1)] {
+        if get_fluid(api, offset).is_some_and(|below| below.density < material.density) {
+            if api.rng.gen::<f32>() < material.sink_chance {
+                return Some(LiveTileInstruction::Switch(offset));
+            }
 
-        WaterTile {
-            color: color.into(),
-            frames_since_color_change: 0
+            api.keep_awake();
+            return None;
         }
     }
 
-    pub fn update(&mut self, api: LiveTileApi) -> LiveTileInstruction {
-        let random_direction = thread_rng().gen_range(-2, 2);
+    None
+}
 
-        self.frames_since_color_change += 1;
-        if self.frames_since_color_change >= 45 {
-            let color: Lch = WATER_GRADIENT.get(thread_rng().gen_range(0.0, 1.0));
-            let color: Rgb = color.into();
-            self.color = color.into();
-            self.frames_since_color_change = 0;
+pub fn update_powder(tile: &mut LiveTile, mut api: LiveTileApi) -> LiveTileInstruction {
+    let tile_below = api.get(Vector2::new(0, 1));
+    
+    if tile_below == Tile::Empty {
+        return LiveTileInstruction::Replace(Vector2::new(0, 1));
+    } else {
+        let random_direction = if api.rng.gen() { -1 } else { 1 };
+        if api.get(Vector2::new(random_direction, 1)) == Tile::Empty {
+            return LiveTileInstruction::Replace(Vector2::new(random_direction, 1));
         }
+    }
 
-        if api.is_empty(Vector2::new(0, 1)) { return LiveTileInstruction::Replace(Vector2::new(0, 1)); } 
-        else if api.is_empty(Vector2::new(random_direction, 1)) { return LiveTileInstruction::Replace(Vector2::new(random_direction, 1)); } 
-        else if api.is_empty(Vector2::new(-random_direction, 1)) { return LiveTileInstruction::Replace(Vector2::new(-random_direction, 1)); } 
-        else if api.is_empty(Vector2::new(random_direction, 0)) { return LiveTileInstruction::Replace(Vector2::new(random_direction, 0)); } 
-        else if api.is_empty(Vector2::new(-random_direction, 0)) { return LiveTileInstruction::Replace(Vector2::new(-random_direction, 0)); }
+    if let Some(instruction) = sink(tile, &mut api) {
+        return instruction;
+    }
 
-        LiveTileInstruction::None
+    // Only one diagonal is tried per tick, the other one may still be free
+    if api.is_empty(Vector2::new(-1, 1)) || api.is_empty(Vector2::new(1, 1)) {
+        api.keep_awake();
     }
+
+    LiveTileInstruction::None
+}
+
+pub fn update_liquid(tile: &mut LiveTile, mut api: LiveTileApi) -> LiveTileInstruction {
+    if api.is_empty(Vector2::new(0, 1)) {
+        return LiveTileInstruction::Replace(Vector2::new(0, 1));
+    }
+    if let Some(instruction) = sink(tile, &mut api) {
+        return instruction;
+    }
+
+    // Thick liquids only spread out some of the time
+    let viscosity = api.materials.get(tile.material).viscosity;
+    if viscosity > 0.0 && api.rng.gen::<f32>() < viscosity {
+        api.keep_awake();
+        return LiveTileInstruction::None;
+    }
+
+    // One or two tiles to either side, evenly
+    let distance = api.rng.gen_range(1, 3);
+    let random_direction = if api.rng.gen() { distance } else { -distance };
+
+    if api.is_empty(Vector2::new(random_direction, 1)) { return LiveTileInstruction::Replace(Vector2::new(random_direction, 1)); } 
+    else if api.is_empty(Vector2::new(-random_direction, 1)) { return LiveTileInstruction::Replace(Vector2::new(-random_direction, 1)); } 
+    else if api.is_empty(Vector2::new(random_direction, 0)) { return LiveTileInstruction::Replace(Vector2::new(random_direction, 0)); } 
+    else if api.is_empty(Vector2::new(-random_direction, 0)) { return LiveTileInstruction::Replace(Vector2::new(-random_direction, 0)); }
+
+    // Not every direction is tried every tick
+    if [-2, -1, 1, 2].iter().any(|&x| api.is_empty(Vector2::new(x, 1)) || api.is_empty(Vector2::new(x, 0))) {
+        api.keep_awake();
+    }
+
+    LiveTileInstruction::None
+}
+
+/// Rises with some sideways drift and spreads out sideways when it's trapped under a ceiling.
+/// Heavier liquids and gases above sink through it, and it sinks through lighter gases.
+pub fn update_gas(tile: &mut LiveTile, mut api: LiveTileApi) -> LiveTileInstruction {
+    let drift = api.rng.gen_range(-1, 2);
+
+    for &offset in &[Vector2::new(drift, -1), Vector2::new(0, -1), Vector2::new(-drift, -1)] {
+        if api.is_empty(offset) {
+            return LiveTileInstruction::Replace(offset);
+        }
+    }
+
+    if let Some(instruction) = sink(tile, &mut api) {
+        return instruction;
+    }
+
+    let side = if api.rng.gen() { -1 } else { 1 };
+    if api.is_empty(Vector2::new(side, 0)) { return LiveTileInstruction::Replace(Vector2::new(side, 0)); }
+    else if api.is_empty(Vector2::new(-side, 0)) { return LiveTileInstruction::Replace(Vector2::new(-side, 0)); }
+
+    // Without drift the diagonals above aren't tried
+    if api.is_empty(Vector2::new(-1, -1)) || api.is_empty(Vector2::new(1, -1)) {
+        api.keep_awake();
+    }
+
+    LiveTileInstruction::None
 }
 
 // #[derive(Copy, Clone, Debug, PartialEq)]
@@ -1,79 +1,54 @@
 #![windows_subsystem = "windows"]
 
 use pixels::{Error, Pixels, SurfaceTexture};
-use winit::dpi::{LogicalPosition, LogicalSize, PhysicalSize};
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit_input_helper::WinitInputHelper;
 use std::time::Instant;
-use crate::world::{World};
-use crate::tile::{Tile, StaticTile, LiveTile, LiveTileState};
-use crate::live_tiles::{SandTile, WaterTile};
-use crate::gui::Gui;
-use crate::particle::Particle;
+use powpowder::{World, MaterialRegistry, SimulationClock, BACKGROUND_COLOR};
+use powpowder::material::MaterialError;
+use powpowder::brush::Brush;
+use powpowder::import::{Palette, ImageFit};
+use powpowder::camera::Camera;
+use powpowder::save::MAX_WORLD_SIZE;
+use crate::gui::{Gui, GuiCommand};
 use cgmath::Vector2;
-use rand::{thread_rng, Rng};
-use palette::rgb::Rgb;
 use log::error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::process;
+use std::sync::Arc;
 
-mod world;
-mod live_tiles;
-mod tile;
-mod particle;
 mod gui;
 
-#[derive(PartialEq, Copy, Clone)]
-pub enum SelectedMaterial {
-    Stone,
-    Sand,
-    Water
-}
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub struct Color {
-    r: u8,
-    g: u8,
-    b: u8
-}
-
-impl Color {
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
-        Self {
-            r,
-            g,
-            b
-        }
-    }
-}
-
-impl From<Rgb> for Color {
-    fn from(rgb_color: Rgb) -> Color {
-        let (r, g, b) = rgb_color.into();
-        let r: u8 = (r * 255.0) as u8;
-        let g: u8 = (g * 255.0) as u8;
-        let b: u8 = (b * 255.0) as u8;
+/// Materials are read from this file in the working directory when it exists.
+const MATERIALS_PATH: &str = "materials.ron";
 
-        Color::new(r, g, b)
-    }
-}
+/// How fast the arrow keys move the camera, in screen pixels per second.
+const CAMERA_PAN_SPEED: f32 = 300.0;
 
-impl From<(u8, u8, u8)> for Color {    
-    fn from(rgb_color: (u8, u8, u8)) -> Color {
-        let (r, g, b) = rgb_color;
-        Color::new(r, g, b)
-    }
-}
+const USAGE: &str = "\
+Usage: powpowder [options]
 
-const BACKGROUND_COLOR: Color = Color { r: 234, g: 231, b: 217 };
+Options:
+    --width <tiles>    World width (default 400)
+    --height <tiles>   World height (default 300)
+    -h, --help         Print this message";
 
 fn main() -> Result<(), Error> {
+    let (world_width, world_height) = match world_size_from_args(std::env::args().skip(1)) {
+        Ok(world_size) => world_size,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     
-    let world_width = 400;
-    let world_height = 300;
-    
-    let (window, mut physical_width, mut physical_height, mut scale_factor) =
+    let (window, physical_width, physical_height) =
         create_window("Powpowder", &event_loop, world_width, world_height);
 
 
@@ -82,15 +57,31 @@ fn main() -> Result<(), Error> {
         Pixels::new(world_width, world_height, surface_texture)? 
     };
     
-    let mut world = World::new(BACKGROUND_COLOR, world_width, world_height);
-    let mut current_frame: u64 = 0;
+    let materials = load_materials();
+    let mut world = World::with_materials(BACKGROUND_COLOR, world_width, world_height, rand::random(), materials.clone());
+    let mut clock = SimulationClock::default();
     let mut last_time_updated = Instant::now();
     
-    let mut gui = Gui::new(&window, &pixels);
+    let mut gui = Gui::new(&window, &pixels, materials.clone());
+    gui.set_world_size(world_width, world_height);
+    // Size of the pixel buffer, which has to follow the size of the world
+    let mut pixels_size = (world_width, world_height);
+
+    // The world is drawn into a frame of its own, of which the camera shows a part
+    let mut world_frame = vec![0; (world_width * world_height * 4) as usize];
+    let mut camera = Camera::new(Vector2::new(world_width, world_height), Vector2::new(world_width, world_height));
     
     event_loop.run(move |event, _, control_flow| {
         if let Event::RedrawRequested(_) = event {
-            world.render(pixels.get_frame());
+            if gui.get_show_temperature() {
+                world.render_temperature(&mut world_frame);
+            } else {
+                world.render(&mut world_frame);
+            }
+            if gui.get_show_chunks() {
+                world.render_chunks(&mut world_frame);
+            }
+            camera.render(&world_frame, pixels.get_frame());
 
             gui.prepare(&window).expect("gui.prepare() failed");
 
@@ -118,162 +109,248 @@ fn main() -> Result<(), Error> {
             .handle_event(gui.imgui.io_mut(), &window, &event);
 
         if input.update(event) {
-            current_frame += 1;
-
             let current_time = Instant::now();
             let delta_time = current_time.duration_since(last_time_updated).as_secs_f32();
             last_time_updated = current_time;
             
             println!("FPS: {:?}", 1.0/ delta_time);
 
-            world.update(delta_time, current_frame);
+            if input.key_pressed(VirtualKeyCode::Space) {
+                clock.toggle_paused();
+            }
+
+            if input.key_pressed(VirtualKeyCode::Period) {
+                clock.step();
+            }
+
+            if input.key_pressed(VirtualKeyCode::Equals) {
+                clock.set_time_scale((clock.time_scale() * 2.0).min(8.0));
+            } else if input.key_pressed(VirtualKeyCode::Minus) {
+                clock.set_time_scale((clock.time_scale() / 2.0).max(0.125));
+            }
+
+            clock.advance(&mut world, delta_time);
             
             if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
             
-            let selected_material = gui.get_selected_material();
-            
-            let mouse_position =  input
-                .mouse()
-                .and_then(|(mx, my)| {
-                    let dpx = scale_factor as f32;
-                    let (w, h) = (physical_width as f32 / dpx, physical_height as f32 / dpx);
-                    let mx_i = ((mx / w) * (world_width as f32)).round() as isize;
-                    let my_i = ((my / h) * (world_height as f32)).round() as isize;
-
-                    let mouse_position: Option<Vector2<u32>> = Vector2::new(mx_i, my_i).cast();
-                    mouse_position
-                });
-
-            if input.mouse_held(0) && selected_material == SelectedMaterial::Sand {
-                if let Option::Some(mouse_position) = mouse_position {
-                    for _ in 0..10 {
-                        world.add_particle(
-                            Particle::new(
-                                Tile::LiveTile(
-                                    LiveTile::new(
-                                        LiveTileState::Sand(SandTile::new())
-                                    )
-                                ),
-                                mouse_position.cast().unwrap(),
-                                Vector2::new(thread_rng().gen_range(-30.0, 30.0), thread_rng().gen_range(-30.0, 30.0))
-                            )
-                        );
+            let ctrl_held = input.held_control();
+            let command = gui.take_command()
+                .or_else(|| if ctrl_held && input.key_pressed(VirtualKeyCode::S) { Some(GuiCommand::SaveWorld) } else { None })
+                .or_else(|| if ctrl_held && input.key_pressed(VirtualKeyCode::O) { Some(GuiCommand::OpenWorld) } else { None });
+
+            match command {
+                Some(GuiCommand::SaveWorld) => {
+                    let path = gui.get_world_path().to_string();
+                    let result = File::create(&path)
+                        .map_err(Into::into)
+                        .and_then(|file| world.save_to(BufWriter::new(file)));
+
+                    match result {
+                        Ok(()) => gui.set_status(format!("Saved {}", path)),
+                        Err(e) => gui.set_status(format!("Couldn't save {}: {}", path, e))
                     }
                 }
-            } else if input.mouse_held(0) && selected_material == SelectedMaterial::Water {
-                if let Option::Some(mouse_position) = mouse_position {
-                    for _ in 0..10 {
-                        world.add_particle(
-                            Particle::new(
-                                Tile::LiveTile(
-                                    LiveTile::new(
-                                        LiveTileState::Water(WaterTile::new())
-                                    )
-                                ),
-                                mouse_position.cast().unwrap(),
-                                Vector2::new(thread_rng().gen_range(-30.0, 30.0), thread_rng().gen_range(-30.0, 30.0))
-                            )
-                        );
+                Some(GuiCommand::OpenWorld) => {
+                    let path = gui.get_world_path().to_string();
+                    let result = File::open(&path)
+                        .map_err(Into::into)
+                        .and_then(|file| World::load_from(BufReader::new(file), materials.clone()));
+
+                    match result {
+                        Ok(loaded_world) => {
+                            world = loaded_world;
+                            gui.set_boundaries(world.boundaries());
+                            gui.set_world_size(world.world_width, world.world_height);
+                            gui.set_status(format!("Opened {}", path));
+                        }
+                        Err(e) => gui.set_status(format!("Couldn't open {}: {}", path, e))
                     }
                 }
-            } else if input.mouse_held(0) && selected_material == SelectedMaterial::Stone {
-                if let Option::Some(mouse_position) = mouse_position {
-                    for x in 0..3 {
-                        for y in 0..3 {
-                            let nx = mouse_position.x + x - 1;
-                            let ny = mouse_position.y + y - 1;
-
-                            if nx >= world.world_width || ny >= world.world_height {
-                                continue;
-                            }
-
-                            world.set_tile(Vector2::new(nx, ny), Tile::StaticTile(StaticTile::new((48, 47, 43).into())));
-                        }
+                Some(GuiCommand::ImportImage) => {
+                    let path = gui.get_world_path().to_string();
+
+                    match world.import_image_file(&path, &Palette::standard(&materials), ImageFit::Resize) {
+                        Ok(()) => gui.set_status(format!("Imported {}", path)),
+                        Err(e) => gui.set_status(format!("Couldn't import {}: {}", path, e))
                     }
                 }
+                Some(GuiCommand::SetBoundaries(boundaries)) => world.set_boundaries(boundaries),
+                Some(GuiCommand::ResizeWorld { width, height, anchor }) => world.resize(width, height, anchor),
+                Some(GuiCommand::NewWorld { width, height }) => {
+                    let boundaries = world.boundaries();
+                    world = World::with_materials(BACKGROUND_COLOR, width, height, rand::random(), materials.clone());
+                    world.set_boundaries(boundaries);
+                }
+                None => {}
             }
-            
-            if input.mouse_held(1) {
-                if let Option::Some(mouse_position) = mouse_position {
-                    for x in 0..5 {
-                        for y in 0..5 {
-                            let nx = mouse_position.x + x - 2;
-                            let ny = mouse_position.y + y - 2;
-
-                            if nx >= world.world_width || ny >= world.world_height {
-                                continue;
-                            }
-
-                            world.set_tile(Vector2::new(nx, ny), Tile::Empty);
-                        }
+
+            if pixels_size != (world.world_width, world.world_height) {
+                pixels_size = (world.world_width, world.world_height);
+
+                let (physical_width, physical_height) = fit_window(&window, world.world_width, world.world_height);
+                let surface_texture = SurfaceTexture::new(physical_width, physical_height, &window);
+                pixels = match Pixels::new(world.world_width, world.world_height, surface_texture) {
+                    Ok(pixels) => pixels,
+                    Err(e) => {
+                        error!("Couldn't recreate the pixel buffer: {}", e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
                     }
+                };
+                gui.recreate_renderer(&pixels);
+
+                world_frame = vec![0; (world.world_width * world.world_height * 4) as usize];
+                let world_size = Vector2::new(world.world_width, world.world_height);
+                camera.set_sizes(world_size, world_size);
+            }
+
+            let selected_material = gui.get_selected_material();
+            
+            // The cursor is in physical pixels, the pixel buffer is scaled and centered in the
+            // window and the camera shows part of the world on it
+            let screen_position = input
+                .mouse()
+                .and_then(|mouse| pixels.window_pos_to_pixel(mouse).ok())
+                .map(|(x, y)| Vector2::new(x as f32, y as f32));
+
+            let scroll = input.scroll_diff();
+            if let Some(screen_position) = screen_position {
+                if scroll > 0.0 {
+                    camera.zoom_around(camera.zoom() + 1, screen_position);
+                } else if scroll < 0.0 {
+                    camera.zoom_around(camera.zoom() - 1, screen_position);
                 }
             }
 
-            if let Some(factor) = input.scale_factor_changed() {
-                scale_factor = factor;
+            if input.mouse_held(2) {
+                let (dx, dy) = input.mouse_diff();
+                let pixel_scale = pixel_scale(window.inner_size(), pixels_size);
+                camera.pan(Vector2::new(-dx, -dy) / pixel_scale);
+            }
+
+            let pan_distance = CAMERA_PAN_SPEED * delta_time;
+            let mut pan = Vector2::new(0.0, 0.0);
+            if input.key_held(VirtualKeyCode::Left) { pan.x -= pan_distance; }
+            if input.key_held(VirtualKeyCode::Right) { pan.x += pan_distance; }
+            if input.key_held(VirtualKeyCode::Up) { pan.y -= pan_distance; }
+            if input.key_held(VirtualKeyCode::Down) { pan.y += pan_distance; }
+            camera.pan(pan);
+
+            let mouse_position = screen_position.and_then(|screen_position| camera.tile_at(screen_position));
+
+            if let Some(mouse_position) = mouse_position {
+                if input.mouse_held(0) {
+                    if let Some(selected_material) = selected_material {
+                        Brush::for_material(&materials, selected_material).apply(&mut world, mouse_position);
+                    }
+                }
+
+                if input.mouse_held(1) {
+                    Brush::eraser(2).apply(&mut world, mouse_position);
+                }
             }
 
             if let Some(size) = input.window_resized() {
-                physical_width = size.width;
-                physical_height = size.height;
-                pixels.resize(physical_width, physical_height);
+                pixels.resize(size.width, size.height);
             }
 
             window.request_redraw();
-        } else {}
+        }
     });
 }
 
+fn load_materials() -> Arc<MaterialRegistry> {
+    match MaterialRegistry::load(MATERIALS_PATH) {
+        Ok(materials) => Arc::new(materials),
+        Err(MaterialError::Io(e)) if e.kind() == io::ErrorKind::NotFound => MaterialRegistry::builtin(),
+        Err(e) => {
+            error!("Couldn't load {}, using the built-in materials: {}", MATERIALS_PATH, e);
+            MaterialRegistry::builtin()
+        }
+    }
+}
+
+/// How many physical pixels of the window a pixel of the buffer covers, the way `Pixels` scales it.
+fn pixel_scale(window_size: PhysicalSize<u32>, pixels_size: (u32, u32)) -> f32 {
+    let scale = (window_size.width as f32 / pixels_size.0 as f32).min(window_size.height as f32 / pixels_size.1 as f32);
+    scale.max(1.0).floor()
+}
+
+fn world_size_from_args<I: Iterator<Item = String>>(mut args: I) -> Result<(u32, u32), String> {
+    let (mut world_width, mut world_height) = (400, 300);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+
+        match arg.as_str() {
+            "--width" => world_width = value()?.parse().map_err(|_| format!("invalid value for {}", arg))?,
+            "--height" => world_height = value()?.parse().map_err(|_| format!("invalid value for {}", arg))?,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option {}", arg))
+        }
+    }
+
+    if world_width == 0 || world_height == 0 {
+        return Err("world size must be at least 1x1".to_string());
+    }
+    if world_width > MAX_WORLD_SIZE || world_height > MAX_WORLD_SIZE {
+        return Err(format!("world size must be at most {}x{}", MAX_WORLD_SIZE, MAX_WORLD_SIZE));
+    }
+
+    Ok((world_width, world_height))
+}
+
 fn create_window(
     title: &str,
     event_loop: &EventLoop<()>,
     world_width: u32,
     world_height: u32
-) -> (winit::window::Window, u32, u32, f64) {
+) -> (winit::window::Window, u32, u32) {
     // Create a hidden window so we can estimate a good default window size
     let window = winit::window::WindowBuilder::new()
         .with_visible(false)
         .with_title(title)
-        .build(&event_loop)
+        .build(event_loop)
         .unwrap();
     let scale_factor = window.scale_factor();
 
+    let (physical_width, physical_height) = fit_window(&window, world_width, world_height);
+
+    // Center and display the window
+    let monitor_size = window.current_monitor().size();
+    let center: LogicalPosition<f64> = PhysicalPosition::new(
+        (f64::from(monitor_size.width) - f64::from(physical_width)) / 2.0,
+        (f64::from(monitor_size.height) - f64::from(physical_height)) / 2.0,
+    ).to_logical(scale_factor);
+    window.set_outer_position(center);
+    window.set_visible(true);
+
+    (window, physical_width, physical_height)
+}
+
+/// Sizes the window to show a world of `world_width` by `world_height` at the integer scale
+/// that takes up about two thirds of the monitor's height and returns its physical size.
+fn fit_window(window: &winit::window::Window, world_width: u32, world_height: u32) -> (u32, u32) {
+    let scale_factor = window.scale_factor();
+
     // Get dimensions
     let width = world_width as f64;
     let height = world_height as f64;
-    let (monitor_width, monitor_height) = {
-        let size = window.current_monitor().size();
-        (
-            (size.width as f64) / scale_factor,
-            (size.height as f64) / scale_factor,
-        )
-    };
-    let scale = (monitor_height / height * 2.0 / 3.0).round();
+    let monitor_height = window.current_monitor().size().height as f64 / scale_factor;
+    let scale = (monitor_height / height * 2.0 / 3.0).round().max(1.0);
 
-    // Resize, center, and display the window
     let min_size: LogicalSize<f64> = PhysicalSize::new(width, height).to_logical(scale_factor);
     let default_size = LogicalSize::new(width * scale, height * scale);
-    let center = LogicalPosition::new(
-        (monitor_width - width * scale) / 2.0,
-        (monitor_height - height * scale) / 2.0,
-    );
-    window.set_inner_size(default_size);
     window.set_min_inner_size(Some(min_size));
-    window.set_outer_position(center);
-    window.set_visible(true);
+    window.set_inner_size(default_size);
 
-    // let surface = pixels::wgpu::Surface::create(&window);
     let size: PhysicalSize<f64> = default_size.to_physical(scale_factor);
 
-    (
-        window,
-        // surface,
-        size.width.round() as u32,
-        size.height.round() as u32,
-        scale_factor,
-    )
+    (size.width.round() as u32, size.height.round() as u32)
 }
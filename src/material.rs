@@ -0,0 +1,571 @@
+//! Data-driven material definitions.
+//!
+//! Materials are declared in a RON file (see `assets/materials.ron` for the built-in set) and
+//! collected in a `MaterialRegistry`. Tiles refer to their material by `MaterialId`, an index
+//! into the registry, and behave according to the material's `Behavior`.
+
+use crate::Color;
+use crate::tile::{Tile, StaticTile, LiveTile};
+use crate::reaction::{Reaction, ReactionDefinition};
+use crate::schedule::MAX_REACH;
+use lazy_static::lazy_static;
+use palette::{Lch, Gradient};
+use palette::rgb::Rgb;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+const BUILTIN_MATERIALS_SOURCE: &str = include_str!("../assets/materials.ron");
+
+lazy_static! {
+    static ref BUILTIN_MATERIALS: Arc<MaterialRegistry> = Arc::new(
+        MaterialRegistry::from_ron_str(BUILTIN_MATERIALS_SOURCE).expect("built-in materials are valid")
+    );
+}
+
+/// Index of a material in its `MaterialRegistry`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(pub u16);
+
+impl MaterialId {
+    /// Material of the solid wall around the world, it isn't part of any registry.
+    pub const BOUNDARY: MaterialId = MaterialId(u16::MAX);
+}
+
+/// How tiles of a material move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum Behavior {
+    /// Falls straight down or diagonally and piles up.
+    Powder,
+    /// Falls and spreads sideways to fill containers.
+    Liquid,
+    /// Rises and spreads sideways along ceilings.
+    Gas,
+    /// Stays in place and burns, setting flammable neighbours alight. Goes out when it touches a
+    /// liquid that doesn't burn itself.
+    Fire,
+    /// Never moves.
+    Static
+}
+
+/// What drawing with a material does by default.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum BrushKind {
+    /// Fills the square of tiles within `radius` of the cursor.
+    Fill { radius: u32 },
+    /// Throws `amount` particles per tick with a random velocity of up to `speed` in each direction.
+    Spray { amount: u32, speed: f32 }
+}
+
+/// A temperature a material changes phase at.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum Threshold {
+    Above(f32),
+    Below(f32)
+}
+
+impl Threshold {
+    pub fn is_passed(self, temperature: f32) -> bool {
+        match self {
+            Threshold::Above(threshold) => temperature > threshold,
+            Threshold::Below(threshold) => temperature < threshold
+        }
+    }
+
+    pub fn temperature(self) -> f32 {
+        match self {
+            Threshold::Above(threshold) | Threshold::Below(threshold) => threshold
+        }
+    }
+}
+
+/// A phase transition as it is written in a materials file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransitionDefinition {
+    /// Id of the material the tile turns into.
+    pub into: String,
+    pub when: Threshold,
+    /// Heat the tile has to take in (or give off, when cooling) at the threshold temperature
+    /// before it changes.
+    pub latent_heat: f32
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhaseTransition {
+    pub into: MaterialId,
+    pub when: Threshold,
+    pub latent_heat: f32
+}
+
+/// How a flammable material catches fire, as it is written in a materials file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FlammabilityDefinition {
+    /// Id of the fire material a burning tile turns into.
+    pub burns_as: String,
+    /// Chance per tick and burning neighbour that the tile catches fire.
+    pub ignition_chance: f32,
+    /// Ticks the fire lasts once the tile has caught.
+    pub burn_duration: u16,
+    /// Temperature the tile catches fire at on its own, without a burning neighbour.
+    #[serde(default)]
+    pub ignition_temperature: Option<f32>
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Flammability {
+    pub burns_as: MaterialId,
+    pub ignition_chance: f32,
+    pub burn_duration: u16,
+    pub ignition_temperature: Option<f32>
+}
+
+/// How a corrosive material dissolves its neighbours.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub struct Corrosion {
+    /// Chance per tick of dissolving a neighbour without any acid resistance.
+    pub chance: f32,
+    /// Number of tiles a single tile can dissolve before it's used up.
+    pub strength: u16
+}
+
+/// How a flammable material explodes instead of burning, as it is written in a materials file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExplosionDefinition {
+    /// Tiles within this many tiles are hit by the blast, at most `schedule::MAX_REACH`.
+    pub radius: u16,
+    /// Speed, in tiles per second, tiles right next to the explosion are thrown out with.
+    pub force: f32,
+    /// Heat added to the cells next to the explosion, less further out.
+    #[serde(default)]
+    pub heat: f32,
+    /// Id of the material the exploding tile leaves behind, nothing if left out.
+    #[serde(default)]
+    pub leaves: Option<String>
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Explosion {
+    pub radius: u16,
+    pub force: f32,
+    pub heat: f32,
+    pub leaves: Option<MaterialId>
+}
+
+/// A material as it is written in a materials file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MaterialDefinition {
+    pub id: String,
+    pub name: String,
+    pub colors: Vec<(u8, u8, u8)>,
+    pub density: f32,
+    pub behavior: Behavior,
+    #[serde(default = "default_sink_chance")]
+    pub sink_chance: f32,
+    #[serde(default)]
+    pub viscosity: f32,
+    #[serde(default)]
+    pub recolor_interval: Option<u16>,
+    #[serde(default = "default_conductivity")]
+    pub conductivity: f32,
+    #[serde(default = "default_heat_capacity")]
+    pub heat_capacity: f32,
+    #[serde(default)]
+    pub initial_temperature: Option<f32>,
+    #[serde(default)]
+    pub source_temperature: Option<f32>,
+    #[serde(default)]
+    pub transitions: Vec<TransitionDefinition>,
+    #[serde(default)]
+    pub lifetime: Option<u16>,
+    #[serde(default)]
+    pub decays_into: Option<String>,
+    #[serde(default)]
+    pub flammability: Option<FlammabilityDefinition>,
+    #[serde(default)]
+    pub quenched_into: Option<String>,
+    #[serde(default)]
+    pub explosion: Option<ExplosionDefinition>,
+    #[serde(default)]
+    pub corrosion: Option<Corrosion>,
+    #[serde(default)]
+    pub acid_resistance: f32,
+    #[serde(default)]
+    pub blast_resistance: f32,
+    pub brush: BrushKind
+}
+
+fn default_sink_chance() -> f32 {
+    0.5
+}
+
+fn default_conductivity() -> f32 {
+    0.1
+}
+
+fn default_heat_capacity() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct MaterialsFile {
+    materials: Vec<MaterialDefinition>,
+    #[serde(default)]
+    reactions: Vec<ReactionDefinition>
+}
+
+#[derive(Debug)]
+pub struct Material {
+    /// Stable identifier used in files, e.g. `"sand"`.
+    pub id: String,
+    /// Name shown to users.
+    pub name: String,
+    /// Heavier movable tiles sink through lighter liquids and gases.
+    pub density: f32,
+    pub behavior: Behavior,
+    /// Chance per tick that a tile sinks into a lighter liquid or gas below it.
+    pub sink_chance: f32,
+    /// Chance per tick that a liquid tile doesn't spread out, from 0 (flows like water) to 1 (never spreads).
+    pub viscosity: f32,
+    pub recolor_interval: Option<u16>,
+    /// How readily heat crosses into neighbouring tiles, from 0 (insulator) to 1.
+    pub conductivity: f32,
+    /// How much heat it takes to change the temperature of a tile by one degree.
+    pub heat_capacity: f32,
+    /// Temperature of newly placed tiles. `None` leaves the temperature of the cell they're placed in.
+    pub initial_temperature: Option<f32>,
+    /// Temperature tiles of the material hold their cell at every tick, heating or cooling their surroundings.
+    pub source_temperature: Option<f32>,
+    pub transitions: Vec<PhaseTransition>,
+    /// Ticks a tile lasts before it turns into `decays_into`, new tiles get between half and all of it.
+    pub lifetime: Option<u16>,
+    /// What a tile turns into at the end of its lifetime, `None` for nothing.
+    pub decays_into: Option<MaterialId>,
+    pub flammability: Option<Flammability>,
+    /// What a fire tile turns into when a liquid puts it out, `None` for nothing.
+    pub quenched_into: Option<MaterialId>,
+    /// What happens when the material catches fire, if it explodes rather than burns.
+    pub explosion: Option<Explosion>,
+    pub corrosion: Option<Corrosion>,
+    /// How well the material stands up to corrosive neighbours, from 0 to 1 (immune).
+    pub acid_resistance: f32,
+    /// How well the material stands up to explosions, from 0 to 1 (never moved).
+    pub blast_resistance: f32,
+    /// Reactions tiles of the material evaluate with their neighbours.
+    pub reactions: Vec<Reaction>,
+    pub brush: BrushKind,
+    gradient: Gradient<Lch>
+}
+
+impl Material {
+    fn from_definition(definition: MaterialDefinition, ids: &HashMap<String, MaterialId>) -> Result<Material, MaterialError> {
+        if definition.colors.is_empty() {
+            return Err(MaterialError::Invalid(format!("material {} has no colors", definition.id)));
+        }
+        if !(0.0..=1.0).contains(&definition.sink_chance) {
+            return Err(MaterialError::Invalid(format!("material {} has a sink chance outside 0 to 1", definition.id)));
+        }
+        if !(0.0..=1.0).contains(&definition.viscosity) {
+            return Err(MaterialError::Invalid(format!("material {} has a viscosity outside 0 to 1", definition.id)));
+        }
+        if !(0.0..=1.0).contains(&definition.acid_resistance) {
+            return Err(MaterialError::Invalid(format!("material {} has an acid resistance outside 0 to 1", definition.id)));
+        }
+        if !(0.0..=1.0).contains(&definition.blast_resistance) {
+            return Err(MaterialError::Invalid(format!("material {} has a blast resistance outside 0 to 1", definition.id)));
+        }
+        if definition.explosion.is_some() && definition.flammability.is_none() {
+            return Err(MaterialError::Invalid(format!("material {} explodes but isn't flammable", definition.id)));
+        }
+        if definition.explosion.as_ref().is_some_and(|explosion| explosion.radius as u32 > MAX_REACH) {
+            return Err(MaterialError::Invalid(format!("material {} has an explosion radius above {}", definition.id, MAX_REACH)));
+        }
+        if definition.explosion.as_ref().is_some_and(|explosion| !(explosion.force >= 0.0 && explosion.force.is_finite())) {
+            return Err(MaterialError::Invalid(format!("material {} has an explosion force that is negative or not finite", definition.id)));
+        }
+        if definition.explosion.as_ref().is_some_and(|explosion| !explosion.heat.is_finite()) {
+            return Err(MaterialError::Invalid(format!("material {} has an explosion heat that isn't finite", definition.id)));
+        }
+        if !(0.0..=1.0).contains(&definition.conductivity) {
+            return Err(MaterialError::Invalid(format!("material {} has a conductivity outside 0 to 1", definition.id)));
+        }
+        if definition.heat_capacity <= 0.0 || !definition.heat_capacity.is_finite() {
+            return Err(MaterialError::Invalid(format!("material {} has a heat capacity that isn't positive", definition.id)));
+        }
+        if definition.flammability.as_ref().is_some_and(|flammability| !(0.0..=1.0).contains(&flammability.ignition_chance)) {
+            return Err(MaterialError::Invalid(format!("material {} has an ignition chance outside 0 to 1", definition.id)));
+        }
+        if definition.corrosion.is_some_and(|corrosion| !(0.0..=1.0).contains(&corrosion.chance)) {
+            return Err(MaterialError::Invalid(format!("material {} has a corrosion chance outside 0 to 1", definition.id)));
+        }
+        if let BrushKind::Spray { speed, .. } = definition.brush {
+            if speed <= 0.0 || !speed.is_finite() {
+                return Err(MaterialError::Invalid(format!("material {} has a spray speed that isn't positive", definition.id)));
+            }
+        }
+
+        let resolve = |id: &String| ids.get(id).copied().ok_or_else(|| MaterialError::Invalid(
+            format!("material {} refers to unknown material {}", definition.id, id)
+        ));
+
+        let mut transitions = Vec::with_capacity(definition.transitions.len());
+        for transition in &definition.transitions {
+            if transition.latent_heat < 0.0 || !transition.latent_heat.is_finite() {
+                return Err(MaterialError::Invalid(format!("material {} has a negative latent heat", definition.id)));
+            }
+
+            transitions.push(PhaseTransition { into: resolve(&transition.into)?, when: transition.when, latent_heat: transition.latent_heat });
+        }
+
+        let flammability = match &definition.flammability {
+            Some(flammability) => Some(Flammability {
+                burns_as: resolve(&flammability.burns_as)?,
+                ignition_chance: flammability.ignition_chance,
+                burn_duration: flammability.burn_duration,
+                ignition_temperature: flammability.ignition_temperature
+            }),
+            None => None
+        };
+        let decays_into = definition.decays_into.as_ref().map(resolve).transpose()?;
+        let quenched_into = definition.quenched_into.as_ref().map(resolve).transpose()?;
+        let explosion = match &definition.explosion {
+            Some(explosion) => Some(Explosion {
+                radius: explosion.radius,
+                force: explosion.force,
+                heat: explosion.heat,
+                leaves: explosion.leaves.as_ref().map(resolve).transpose()?
+            }),
+            None => None
+        };
+
+        let colors: Vec<Lch> = definition.colors.iter()
+            .map(|&(r, g, b)| {
+                let color: Rgb = Rgb::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+                color.into()
+            })
+            .collect();
+
+        Ok(Material {
+            id: definition.id,
+            name: definition.name,
+            density: definition.density,
+            behavior: definition.behavior,
+            sink_chance: definition.sink_chance,
+            viscosity: definition.viscosity,
+            recolor_interval: definition.recolor_interval,
+            conductivity: definition.conductivity,
+            heat_capacity: definition.heat_capacity,
+            initial_temperature: definition.initial_temperature,
+            source_temperature: definition.source_temperature,
+            transitions,
+            lifetime: definition.lifetime,
+            decays_into,
+            flammability,
+            quenched_into,
+            explosion,
+            corrosion: definition.corrosion,
+            acid_resistance: definition.acid_resistance,
+            blast_resistance: definition.blast_resistance,
+            reactions: Vec::new(),
+            brush: definition.brush,
+            gradient: Gradient::new(colors)
+        })
+    }
+
+    /// Picks a random color from the material's gradient.
+    pub fn random_color<R: Rng + ?Sized>(&self, rng: &mut R) -> Color {
+        let color: Lch = self.gradient.get(rng.gen_range(0.0, 1.0));
+        let color: Rgb = color.into();
+
+        color.into()
+    }
+
+    pub fn is_movable(&self) -> bool {
+        matches!(self.behavior, Behavior::Powder | Behavior::Liquid | Behavior::Gas)
+    }
+
+    /// Whether tiles of the material ever need updating, to move, burn, corrode, decay, react or change phase.
+    pub fn is_live(&self) -> bool {
+        self.behavior != Behavior::Static
+            || !self.reactions.is_empty()
+            || !self.transitions.is_empty()
+            || self.lifetime.is_some()
+            || self.flammability.is_some()
+            || self.corrosion.is_some()
+            || self.source_temperature.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub enum MaterialError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// The file parsed, but describes an unusable set of materials.
+    Invalid(String)
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialError::Io(error) => write!(f, "i/o error: {}", error),
+            MaterialError::Parse(error) => write!(f, "invalid materials file: {}", error),
+            MaterialError::Invalid(reason) => write!(f, "invalid materials: {}", reason)
+        }
+    }
+}
+
+impl Error for MaterialError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MaterialError::Io(error) => Some(error),
+            MaterialError::Parse(error) => Some(error),
+            MaterialError::Invalid(_) => None
+        }
+    }
+}
+
+impl From<io::Error> for MaterialError {
+    fn from(error: io::Error) -> Self {
+        MaterialError::Io(error)
+    }
+}
+
+impl From<ron::Error> for MaterialError {
+    fn from(error: ron::Error) -> Self {
+        MaterialError::Parse(error)
+    }
+}
+
+/// All materials a world can contain.
+#[derive(Debug)]
+pub struct MaterialRegistry {
+    materials: Vec<Material>,
+    ids: HashMap<String, MaterialId>,
+    boundary: Material
+}
+
+impl MaterialRegistry {
+    /// The materials in `assets/materials.ron`, compiled into the crate.
+    pub fn builtin() -> Arc<MaterialRegistry> {
+        BUILTIN_MATERIALS.clone()
+    }
+
+    pub fn from_definitions(definitions: Vec<MaterialDefinition>, reactions: Vec<ReactionDefinition>) -> Result<MaterialRegistry, MaterialError> {
+        if definitions.len() >= MaterialId::BOUNDARY.0 as usize {
+            return Err(MaterialError::Invalid(format!("too many materials ({})", definitions.len())));
+        }
+
+        let mut ids = HashMap::new();
+        for (index, definition) in definitions.iter().enumerate() {
+            if ids.insert(definition.id.clone(), MaterialId(index as u16)).is_some() {
+                return Err(MaterialError::Invalid(format!("material {} is defined more than once", definition.id)));
+            }
+        }
+
+        // Transitions can refer to materials defined further down, so every id has to be known first
+        let mut materials = definitions.into_iter()
+            .map(|definition| Material::from_definition(definition, &ids))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Decide which side evaluates each reaction before reactions start making materials live
+        let is_live: Vec<bool> = materials.iter().map(Material::is_live).collect();
+        for reaction in &reactions {
+            let (material, reaction) = reaction.resolve(&ids, |material| is_live[material.0 as usize])?;
+            materials[material.0 as usize].reactions.push(reaction);
+        }
+
+        let boundary_color: Rgb = Rgb::new(1.0, 0.0, 0.0);
+        let boundary = Material {
+            id: "boundary".to_string(),
+            name: "Boundary".to_string(),
+            density: f32::INFINITY,
+            behavior: Behavior::Static,
+            sink_chance: 0.0,
+            viscosity: 0.0,
+            recolor_interval: None,
+            conductivity: 0.0,
+            heat_capacity: f32::INFINITY,
+            initial_temperature: None,
+            source_temperature: None,
+            transitions: Vec::new(),
+            lifetime: None,
+            decays_into: None,
+            flammability: None,
+            quenched_into: None,
+            explosion: None,
+            corrosion: None,
+            acid_resistance: 1.0,
+            blast_resistance: 1.0,
+            reactions: Vec::new(),
+            brush: BrushKind::Fill { radius: 0 },
+            gradient: Gradient::new(vec![boundary_color.into()])
+        };
+
+        Ok(MaterialRegistry {
+            materials,
+            ids,
+            boundary
+        })
+    }
+
+    pub fn from_ron_str(source: &str) -> Result<MaterialRegistry, MaterialError> {
+        let file: MaterialsFile = ron::de::from_str(source)?;
+
+        MaterialRegistry::from_definitions(file.materials, file.reactions)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<MaterialRegistry, MaterialError> {
+        MaterialRegistry::from_ron_str(&fs::read_to_string(path)?)
+    }
+
+    /// Looks up a material. Ids from another registry may point at the wrong material or panic.
+    pub fn get(&self, material: MaterialId) -> &Material {
+        if material == MaterialId::BOUNDARY {
+            return &self.boundary;
+        }
+
+        &self.materials[material.0 as usize]
+    }
+
+    /// Finds a material by its file identifier, e.g. `"sand"`.
+    pub fn find(&self, id: &str) -> Option<MaterialId> {
+        self.ids.get(id).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialId, &Material)> {
+        self.materials.iter()
+            .enumerate()
+            .map(|(index, material)| (MaterialId(index as u16), material))
+    }
+
+    /// Creates a new tile of `material` with a random color from its gradient.
+    pub fn create_tile<R: Rng + ?Sized>(&self, material: MaterialId, rng: &mut R) -> Tile {
+        let color = self.get(material).random_color(rng);
+
+        if self.get(material).is_live() {
+            // Vary lifetimes so tiles placed together don't all decay at once
+            let ticks_left = match self.get(material).lifetime {
+                Some(lifetime) => rng.gen_range(lifetime / 2, lifetime.saturating_add(1)),
+                None => 0
+            };
+            let strength = self.get(material).corrosion.map_or(0, |corrosion| corrosion.strength);
+
+            Tile::LiveTile(LiveTile { ticks_left, strength, ..LiveTile::new(material, color) })
+        } else {
+            Tile::StaticTile(StaticTile::new(material, color))
+        }
+    }
+}
@@ -1,6 +1,5 @@
 ﻿use crate::tile::{Tile, LiveTileApi};
 use cgmath::{Vector2, ElementWise};
-use crate::world::World;
 
 #[derive(Copy, Clone, Debug)]
 pub enum ParticleInstructions {
@@ -13,7 +12,7 @@ pub enum ParticleInstructions {
 pub struct Particle {
     pub tile: Tile,
     pub position: Vector2<f32>,
-    velocity: Vector2<f32>
+    pub velocity: Vector2<f32>
 }
 
 impl Particle {
@@ -25,35 +24,35 @@ impl Particle {
         }
     }
     
-    pub fn update(&mut self, delta_time: f32, tiles: &Vec<Tile>, world_width: u32, world_height: u32) -> ParticleInstructions {
+    /// Moves the particle by its velocity, possibly past the edges of the world. See
+    /// `Boundaries::constrain_particle` for bringing it back.
+    pub fn apply_velocity(&mut self, delta_time: f32) {
         self.velocity.y += 200.0 * delta_time;
         self.position.add_assign_element_wise(self.velocity * delta_time);
-        
-        if self.position.x < 0.0 { self.position.x = 0.0; }
-        if self.position.y < 0.0 { self.position.y = 0.0; }
+    }
 
-        let rounded_position: Vector2<u32> = self.position.cast().unwrap();
-        
-        
-        let api = LiveTileApi {
-            tile_position: rounded_position,
-            tiles,
-            world_width,
-            world_height
-        };
-        
+    /// Decides what happens to the particle, with `api` positioned on the tile it is over.
+    pub fn update(&mut self, api: LiveTileApi) -> ParticleInstructions {
         if !api.is_empty(Vector2::new(0, 1)) {
-            for y_offset in 0..10 {
-                if api.is_empty(Vector2::new(0, 1 - y_offset)) { 
-                    let offset: Vector2<u32> = Vector2::new(0, 1 - y_offset).add_element_wise(rounded_position.cast().unwrap()).cast().unwrap();
-                    
-                    return ParticleInstructions::TurnIntoTile(offset);
-                }
-            }
-            
-            return ParticleInstructions::Destroy;
+            return self.land(api);
         }
         
         ParticleInstructions::None
     }
+
+    /// Turns the particle into a tile in the first empty cell at or above the one it is over, or
+    /// destroys it if there's none close by.
+    pub fn land(&self, api: LiveTileApi) -> ParticleInstructions {
+        for y_offset in 0..9 {
+            let offset = Vector2::new(0, -y_offset);
+
+            if api.is_empty(offset) {
+                if let Some(tile_position) = api.get_tile_position(offset) {
+                    return ParticleInstructions::TurnIntoTile(tile_position);
+                }
+            }
+        }
+
+        ParticleInstructions::Destroy
+    }
 }
\ No newline at end of file
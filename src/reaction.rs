@@ -0,0 +1,187 @@
+//! Declarative reactions between neighbouring materials.
+//!
+//! A reaction says that a tile of one material next to a tile of another turns, with some chance
+//! per tick, both of them into new materials, optionally giving off heat and throwing out
+//! particles. Reactions are listed in the materials file next to the materials and evaluated by
+//! `World::update` before a live tile gets to move.
+
+use crate::schedule::ChunkUpdate;
+use crate::tile::{Tile, LiveTile};
+use crate::particle::Particle;
+use crate::command::TileCommand;
+use crate::material::{MaterialId, MaterialError, MaterialRegistry};
+use cgmath::Vector2;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A reaction as it is written in a materials file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ReactionDefinition {
+    /// Ids of the two materials that react when they touch.
+    pub reactants: (String, String),
+    /// Ids of what the two reactants turn into, in the same order. `None` leaves the tile empty.
+    pub products: (Option<String>, Option<String>),
+    /// Chance per tick that a touching pair reacts.
+    pub chance: f32,
+    /// Heat added to each of the two tiles when they react, negative to cool them.
+    #[serde(default)]
+    pub heat: f32,
+    #[serde(default)]
+    pub particles: Option<ParticleEmissionDefinition>
+}
+
+/// Particles a reaction throws out, as written in a materials file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ParticleEmissionDefinition {
+    pub material: String,
+    pub amount: u32,
+    /// Largest speed of the particles in each direction, in tiles per second.
+    pub speed: f32
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParticleEmission {
+    pub material: MaterialId,
+    pub amount: u32,
+    pub speed: f32
+}
+
+/// A reaction as seen from the material whose tiles evaluate it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Reaction {
+    /// Material of the neighbour the tile reacts with.
+    pub with: MaterialId,
+    /// What the tile turns into.
+    pub becomes: Option<MaterialId>,
+    /// What the neighbour turns into.
+    pub neighbour_becomes: Option<MaterialId>,
+    pub chance: f32,
+    pub heat: f32,
+    pub particles: Option<ParticleEmission>
+}
+
+impl ReactionDefinition {
+    /// Resolves the material ids and returns the reactant that evaluates the reaction along with
+    /// the reaction from its point of view.
+    ///
+    /// Only one side evaluates a reaction, so the chance doesn't double when both are live. It's
+    /// the first reactant unless only the second one is live anyway, i.e. `is_live` holds for it.
+    pub(crate) fn resolve<F: Fn(MaterialId) -> bool>(&self, ids: &HashMap<String, MaterialId>, is_live: F) -> Result<(MaterialId, Reaction), MaterialError> {
+        let resolve = |id: &String| ids.get(id).copied().ok_or_else(|| MaterialError::Invalid(
+            format!("reaction between {} and {} refers to unknown material {}", self.reactants.0, self.reactants.1, id)
+        ));
+
+        if !(0.0..=1.0).contains(&self.chance) {
+            return Err(MaterialError::Invalid(format!("reaction between {} and {} has a chance outside 0 to 1", self.reactants.0, self.reactants.1)));
+        }
+
+        let first = resolve(&self.reactants.0)?;
+        let second = resolve(&self.reactants.1)?;
+        let first_product = self.products.0.as_ref().map(resolve).transpose()?;
+        let second_product = self.products.1.as_ref().map(resolve).transpose()?;
+
+        let particles = match &self.particles {
+            Some(particles) if particles.speed <= 0.0 || !particles.speed.is_finite() => {
+                return Err(MaterialError::Invalid(format!("reaction between {} and {} has a particle speed that isn't positive", self.reactants.0, self.reactants.1)));
+            }
+            Some(particles) => Some(ParticleEmission {
+                material: resolve(&particles.material)?,
+                amount: particles.amount,
+                speed: particles.speed
+            }),
+            None => None
+        };
+
+        let reaction = |with, becomes, neighbour_becomes| Reaction {
+            with,
+            becomes,
+            neighbour_becomes,
+            chance: self.chance,
+            heat: self.heat,
+            particles
+        };
+
+        if !is_live(first) && is_live(second) {
+            Ok((second, reaction(first, second_product, first_product)))
+        } else {
+            Ok((first, reaction(second, first_product, second_product)))
+        }
+    }
+}
+
+const NEIGHBOURS: [Vector2<i32>; 4] = [Vector2::new(0, -1), Vector2::new(1, 0), Vector2::new(0, 1), Vector2::new(-1, 0)];
+
+impl ChunkUpdate {
+    /// Lets the tile at `tile_position` react with one of its neighbours. Returns whether it did.
+    pub(crate) fn react(&mut self, tile_position: Vector2<u32>, material: MaterialId, materials: &MaterialRegistry, current_frame: u64) -> bool {
+        let reactions = &materials.get(material).reactions;
+        if reactions.is_empty() {
+            return false;
+        }
+
+        // Start at a random side so no direction is favoured
+        let first_side = self.rng().gen_range(0, NEIGHBOURS.len());
+
+        for side in 0..NEIGHBOURS.len() {
+            let offset = NEIGHBOURS[(first_side + side) % NEIGHBOURS.len()];
+            let neighbour_position = tile_position.cast::<i32>().unwrap() + offset;
+
+            if neighbour_position.x < 0 || neighbour_position.y < 0 || neighbour_position.x >= self.world_width() as i32 || neighbour_position.y >= self.world_height() as i32 {
+                continue;
+            }
+
+            let neighbour_position: Vector2<u32> = neighbour_position.cast().unwrap();
+            let neighbour_material = match self.get_tile(neighbour_position).get_material() {
+                Some(neighbour_material) => neighbour_material,
+                None => continue
+            };
+
+            for reaction in reactions {
+                if reaction.with != neighbour_material {
+                    continue;
+                }
+                if self.rng().gen::<f32>() >= reaction.chance {
+                    // It may well react next tick
+                    self.wake(tile_position);
+                    continue;
+                }
+
+                self.place_product(tile_position, reaction.becomes, materials, current_frame);
+                self.place_product(neighbour_position, reaction.neighbour_becomes, materials, current_frame);
+
+                if reaction.heat != 0.0 {
+                    self.push_command(TileCommand::AddHeat { position: tile_position, heat: reaction.heat });
+                    self.push_command(TileCommand::AddHeat { position: neighbour_position, heat: reaction.heat });
+                }
+
+                if let Some(particles) = reaction.particles {
+                    for _ in 0..particles.amount {
+                        let tile = materials.create_tile(particles.material, self.rng());
+                        let velocity = Vector2::new(
+                            self.rng().gen_range(-particles.speed, particles.speed),
+                            self.rng().gen_range(-particles.speed, particles.speed)
+                        );
+
+                        self.push_command(TileCommand::SpawnParticle(Particle::new(tile, tile_position.cast().unwrap(), velocity)));
+                    }
+                }
+
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn place_product(&mut self, tile_position: Vector2<u32>, product: Option<MaterialId>, materials: &MaterialRegistry, current_frame: u64) {
+        let tile = match product.map(|product| materials.create_tile(product, self.rng())) {
+            // Products don't get to move in the tick they were made in
+            Some(Tile::LiveTile(live_tile)) => Tile::LiveTile(LiveTile { last_frame_updated: current_frame, ..live_tile }),
+            Some(tile) => tile,
+            None => Tile::Empty
+        };
+
+        self.set_tile(tile_position, tile);
+    }
+}
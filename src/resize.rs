@@ -0,0 +1,184 @@
+//! Growing and shrinking a world while it runs.
+
+use crate::world::World;
+use crate::tile::Tile;
+use crate::temperature::AMBIENT_TEMPERATURE;
+use crate::chunk::Chunks;
+use cgmath::Vector2;
+
+/// The part of a world that stays in place when it's resized. Space is added or removed on the
+/// opposite sides, or evenly on both for the middle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    /// Keeps the ground where it is, which suits a world where everything has settled.
+    #[default]
+    Bottom,
+    BottomRight
+}
+
+impl Anchor {
+    /// How far along each axis the anchor lies, 0 at the top left and 2 at the bottom right.
+    fn halves(self) -> Vector2<i64> {
+        match self {
+            Anchor::TopLeft => Vector2::new(0, 0),
+            Anchor::Top => Vector2::new(1, 0),
+            Anchor::TopRight => Vector2::new(2, 0),
+            Anchor::Left => Vector2::new(0, 1),
+            Anchor::Center => Vector2::new(1, 1),
+            Anchor::Right => Vector2::new(2, 1),
+            Anchor::BottomLeft => Vector2::new(0, 2),
+            Anchor::Bottom => Vector2::new(1, 2),
+            Anchor::BottomRight => Vector2::new(2, 2)
+        }
+    }
+}
+
+impl World {
+    /// Changes the size of the world to `world_width` by `world_height`, keeping the tiles,
+    /// temperatures and particles that still fit in place relative to `anchor`. New cells are
+    /// empty and at the ambient temperature.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `world_width` or `world_height` are 0.
+    pub fn resize(&mut self, world_width: u32, world_height: u32, anchor: Anchor) {
+        assert!(world_width > 0 && world_height > 0, "world size must be at least 1x1");
+
+        // Where the old top left corner ends up
+        let halves = anchor.halves();
+        let shift = Vector2::new(
+            (i64::from(world_width) - i64::from(self.world_width)) * halves.x / 2,
+            (i64::from(world_height) - i64::from(self.world_height)) * halves.y / 2
+        );
+
+        let total_amount_of_tiles = world_width as usize * world_height as usize;
+        let mut tiles = vec![Tile::Empty; total_amount_of_tiles];
+        let mut temperatures = vec![AMBIENT_TEMPERATURE; total_amount_of_tiles];
+
+        for y in 0..self.world_height {
+            let new_y = i64::from(y) + shift.y;
+            if new_y < 0 || new_y >= i64::from(world_height) {
+                continue;
+            }
+
+            // The run of this row that is still inside the world
+            let first_x = (-shift.x).max(0) as u32;
+            let last_x = (i64::from(world_width) - shift.x).min(i64::from(self.world_width));
+            if last_x <= i64::from(first_x) {
+                continue;
+            }
+            let last_x = last_x as u32;
+
+            let old_start = (first_x + y * self.world_width) as usize;
+            let old_end = old_start + (last_x - first_x) as usize;
+            let new_start = (i64::from(first_x) + shift.x + new_y * i64::from(world_width)) as usize;
+            let new_end = new_start + (old_end - old_start);
+
+            tiles[new_start..new_end].copy_from_slice(&self.tiles[old_start..old_end]);
+            temperatures[new_start..new_end].copy_from_slice(&self.temperatures[old_start..old_end]);
+        }
+
+        let offset = Vector2::new(shift.x as f32, shift.y as f32);
+        self.particles.retain_mut(|particle| {
+            particle.position += offset;
+
+            let position = particle.position;
+            position.x >= 0.0 && position.y >= 0.0 && position.x < world_width as f32 && position.y < world_height as f32
+        });
+
+        self.frame = vec![0; total_amount_of_tiles * 4];
+        self.tiles = tiles;
+        self.temperatures = temperatures;
+        self.world_width = world_width;
+        self.world_height = world_height;
+
+        for tile_index in 0..total_amount_of_tiles {
+            let tile_position = Vector2::new((tile_index % world_width as usize) as u32, (tile_index / world_width as usize) as u32);
+            self.write_tile(tile_position, self.tiles[tile_index]);
+        }
+
+        // Everything may be able to move again along the new edges
+        self.chunks = Chunks::new(world_width, world_height);
+        self.chunks.set_wraps(self.boundaries.wraps());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::Particle;
+    use crate::BACKGROUND_COLOR;
+
+    /// A 10 by 6 world with a stone at `(4, 3)` and a particle over it.
+    fn marked_world() -> World {
+        let mut world = World::with_seed(BACKGROUND_COLOR, 10, 6, 0);
+        let stone = world.materials().find("stone").unwrap();
+        let tile = world.create_tile(stone);
+        world.set_tile(Vector2::new(4, 3), tile);
+        world.add_particle(Particle::new(tile, Vector2::new(4.5, 1.5), Vector2::new(0.0, 0.0)));
+        world
+    }
+
+    /// Where the stone of `marked_world` is.
+    fn stone_position(world: &World) -> Option<Vector2<u32>> {
+        let index = world.tiles().iter().position(|tile| !matches!(tile, Tile::Empty))?;
+        Some(Vector2::new(index as u32 % world.world_width, index as u32 / world.world_width))
+    }
+
+    #[test]
+    fn growing_shifts_by_the_anchor() {
+        let cases = [
+            (Anchor::TopLeft, Vector2::new(4, 3)),
+            (Anchor::Center, Vector2::new(7, 5)),
+            (Anchor::Bottom, Vector2::new(7, 7)),
+            (Anchor::BottomRight, Vector2::new(10, 7))
+        ];
+
+        for &(anchor, expected) in &cases {
+            let mut world = marked_world();
+            world.resize(16, 10, anchor);
+
+            assert_eq!((world.world_width, world.world_height), (16, 10));
+            assert_eq!(world.tiles().len(), 160);
+            assert_eq!(stone_position(&world), Some(expected), "stone misplaced with {:?}", anchor);
+
+            let offset = expected.cast::<f32>().unwrap() - Vector2::new(4.0, 3.0);
+            assert_eq!(world.particles()[0].position, Vector2::new(4.5, 1.5) + offset, "particle misplaced with {:?}", anchor);
+        }
+    }
+
+    #[test]
+    fn shrinking_drops_what_no_longer_fits() {
+        let mut world = marked_world();
+        world.resize(6, 4, Anchor::TopLeft);
+        assert_eq!(stone_position(&world), Some(Vector2::new(4, 3)));
+        assert_eq!(world.particles().len(), 1);
+
+        let mut world = marked_world();
+        world.resize(6, 4, Anchor::TopRight);
+        assert_eq!(stone_position(&world), Some(Vector2::new(0, 3)));
+
+        let mut world = marked_world();
+        world.resize(4, 4, Anchor::TopLeft);
+        assert_eq!(stone_position(&world), None);
+        assert!(world.particles().is_empty());
+    }
+
+    #[test]
+    fn odd_differences_change_the_bottom_right_more() {
+        let mut world = marked_world();
+        world.resize(13, 9, Anchor::Center);
+        assert_eq!(stone_position(&world), Some(Vector2::new(5, 4)));
+
+        let mut world = marked_world();
+        world.resize(7, 3, Anchor::Center);
+        assert_eq!(stone_position(&world), Some(Vector2::new(3, 2)));
+    }
+}
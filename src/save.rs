@@ -0,0 +1,762 @@
+//! Binary save format for worlds.
+//!
+//! All numbers are little endian. A save file is laid out as:
+//!
+//! ```text
+//! magic           b"POWP"
+//! format version  u16
+//! world width     u32
+//! world height    u32
+//! seed            u64
+//! rng state       u32 length, then that many bytes
+//! materials       u16 count, then `count` x (u16 length, then that many bytes of utf-8 material id)
+//! tile runs       u32 count, then `count` x (u32 run length, tile)
+//! temperatures    u32 count, then `count` x (u32 run length, f32 temperature)
+//! particles       u32 count, then `count` x (tile, position x/y f32, velocity x/y f32)
+//! boundaries      4 x u8 boundary mode of the left, right, top and bottom edge
+//! ```
+//!
+//! The tile grid is run-length encoded in row-major order, so large empty or uniform areas stay small.
+//! Tiles refer to materials by their index in the saved material table, which is matched up with
+//! the loading world's `MaterialRegistry` by id. Materials the registry doesn't define only keep a
+//! save from loading if a tile or particle uses them.
+//!
+//! Version 1 predates the material registry; it has no material table and hard-coded stone, sand
+//! and water tiles. It can still be loaded as long as the registry defines the ones it uses.
+//! Versions 1 and 2 have no temperatures, worlds loaded from them start at the ambient temperature.
+//! Live tiles store the heat they have absorbed towards a phase transition since version 4, and
+//! the ticks left until they decay since version 5. Before version 6 they also stored a sand
+//! specific `under_water_ticks` counter, which is skipped when loading. The strength of corrosive
+//! tiles is stored since version 7. Worlds saved before version 8 have walls on every edge.
+
+use crate::{Color, BACKGROUND_COLOR};
+use crate::world::{World, WorldRng};
+use crate::tile::{Tile, StaticTile, LiveTile};
+use crate::material::{MaterialId, MaterialRegistry};
+use crate::particle::Particle;
+use crate::boundary::{Boundaries, BoundaryMode};
+use cgmath::Vector2;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"POWP";
+
+/// Version written by `World::save_to`. Bump this whenever the layout changes.
+pub const FORMAT_VERSION: u16 = 8;
+
+/// Largest world width or height `World::load_from` accepts, so a corrupt header can't make it
+/// allocate more than a frontend could ever show.
+pub const MAX_WORLD_SIZE: u32 = 4096;
+
+const TILE_EMPTY: u8 = 0;
+const TILE_STATIC: u8 = 1;
+const TILE_LIVE: u8 = 2;
+
+const BOUNDARY_WALL: u8 = 0;
+const BOUNDARY_VOID: u8 = 1;
+const BOUNDARY_WRAP: u8 = 2;
+const BOUNDARY_MIRROR: u8 = 3;
+
+const VERSION_1_TILE_STATIC: u8 = 1;
+const VERSION_1_TILE_SAND: u8 = 2;
+const VERSION_1_TILE_WATER: u8 = 3;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The data doesn't start with the save file magic, so it isn't a world save at all.
+    NotASaveFile,
+    /// The save was written by a format version this build can't read.
+    UnsupportedVersion(u16),
+    /// The save has tiles of a material the world's `MaterialRegistry` doesn't define.
+    UnknownMaterial(String),
+    /// The data ended before the whole world was read.
+    Truncated,
+    /// The data is structurally invalid, e.g. an unknown tile tag or a tile count that doesn't match the world size.
+    Corrupt(String)
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "i/o error: {}", error),
+            SaveError::NotASaveFile => write!(f, "not a powpowder world save"),
+            SaveError::UnsupportedVersion(version) => write!(f, "unsupported save format version {} (this build reads up to version {})", version, FORMAT_VERSION),
+            SaveError::UnknownMaterial(id) => write!(f, "save uses unknown material {}", id),
+            SaveError::Truncated => write!(f, "save file is truncated"),
+            SaveError::Corrupt(reason) => write!(f, "save file is corrupt: {}", reason)
+        }
+    }
+}
+
+impl Error for SaveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SaveError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            SaveError::Truncated
+        } else {
+            SaveError::Io(error)
+        }
+    }
+}
+
+impl World {
+    /// Writes the world, including its particles and RNG state, in the current save format.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SaveError> {
+        let mut writer = SaveWriter { writer };
+
+        writer.bytes(MAGIC)?;
+        writer.u16(FORMAT_VERSION)?;
+        writer.u32(self.world_width)?;
+        writer.u32(self.world_height)?;
+        writer.u64(self.seed)?;
+
+        let rng_state = bincode::serialize(&self.rng)
+            .map_err(|error| SaveError::Io(io::Error::other(error)))?;
+        writer.u32(rng_state.len() as u32)?;
+        writer.bytes(&rng_state)?;
+
+        // Tiles store registry indices as they are, so the table is simply the registry in order
+        writer.u16(self.materials.len() as u16)?;
+        for (_, material) in self.materials.iter() {
+            writer.u16(material.id.len() as u16)?;
+            writer.bytes(material.id.as_bytes())?;
+        }
+
+        let runs = run_length_encode(&self.tiles);
+        writer.u32(runs.len() as u32)?;
+        for (run_length, tile) in runs {
+            writer.u32(run_length)?;
+            writer.tile(tile)?;
+        }
+
+        let temperature_runs = run_length_encode_temperatures(&self.temperatures);
+        writer.u32(temperature_runs.len() as u32)?;
+        for (run_length, temperature) in temperature_runs {
+            writer.u32(run_length)?;
+            writer.f32(temperature)?;
+        }
+
+        writer.u32(self.particles.len() as u32)?;
+        for particle in &self.particles {
+            writer.tile(particle.tile)?;
+            writer.f32(particle.position.x)?;
+            writer.f32(particle.position.y)?;
+            writer.f32(particle.velocity.x)?;
+            writer.f32(particle.velocity.y)?;
+        }
+
+        let boundaries = self.boundaries;
+        for &mode in &[boundaries.left, boundaries.right, boundaries.top, boundaries.bottom] {
+            writer.boundary_mode(mode)?;
+        }
+
+        writer.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads a world written by `World::save_to`, resolving its materials in `materials`.
+    pub fn load_from<R: Read>(reader: R, materials: Arc<MaterialRegistry>) -> Result<World, SaveError> {
+        let mut reader = SaveReader { reader, version: FORMAT_VERSION, materials: Vec::new() };
+
+        let mut magic = [0; 4];
+        reader.reader.read_exact(&mut magic).map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => SaveError::NotASaveFile,
+            _ => SaveError::Io(error)
+        })?;
+        if &magic != MAGIC {
+            return Err(SaveError::NotASaveFile);
+        }
+
+        reader.version = reader.u16()?;
+        if reader.version == 0 || reader.version > FORMAT_VERSION {
+            return Err(SaveError::UnsupportedVersion(reader.version));
+        }
+
+        let world_width = reader.u32()?;
+        let world_height = reader.u32()?;
+        if world_width == 0 || world_height == 0 || world_width > MAX_WORLD_SIZE || world_height > MAX_WORLD_SIZE {
+            return Err(SaveError::Corrupt(format!("invalid world size {}x{}", world_width, world_height)));
+        }
+        let total_amount_of_tiles = world_width * world_height;
+
+        let seed = reader.u64()?;
+
+        let rng_state_length = reader.u32()?;
+        let rng_state = reader.bytes(rng_state_length as usize)?;
+        let rng: WorldRng = bincode::deserialize(&rng_state)
+            .map_err(|error| SaveError::Corrupt(format!("invalid rng state: {}", error)))?;
+
+        if reader.version == 1 {
+            for id in &["stone", "sand", "water"] {
+                reader.materials.push(materials.find(id).ok_or_else(|| id.to_string()));
+            }
+        } else {
+            let material_count = reader.u16()?;
+            for _ in 0..material_count {
+                let id_length = reader.u16()?;
+                let id = reader.string(id_length as usize)?;
+                reader.materials.push(materials.find(&id).ok_or(id));
+            }
+        }
+
+        let mut world = World::with_materials(BACKGROUND_COLOR, world_width, world_height, seed, materials);
+        world.rng = rng;
+
+        let run_count = reader.u32()?;
+        let mut tile_index: u32 = 0;
+        for _ in 0..run_count {
+            let run_length = reader.u32()?;
+            let tile = reader.tile()?;
+
+            let run_end = tile_index.checked_add(run_length)
+                .filter(|&run_end| run_end <= total_amount_of_tiles)
+                .ok_or_else(|| SaveError::Corrupt("tile data exceeds the world size".to_string()))?;
+
+            for index in tile_index..run_end {
+                world.set_tile(Vector2::new(index % world_width, index / world_width), tile);
+            }
+
+            tile_index = run_end;
+        }
+        if tile_index != total_amount_of_tiles {
+            return Err(SaveError::Corrupt(format!("expected {} tiles, found {}", total_amount_of_tiles, tile_index)));
+        }
+
+        if reader.version >= 3 {
+            let run_count = reader.u32()?;
+            let mut tile_index: u32 = 0;
+            for _ in 0..run_count {
+                let run_length = reader.u32()?;
+                let temperature = reader.f32()?;
+
+                let run_end = tile_index.checked_add(run_length)
+                    .filter(|&run_end| run_end <= total_amount_of_tiles)
+                    .ok_or_else(|| SaveError::Corrupt("temperature data exceeds the world size".to_string()))?;
+
+                world.temperatures[tile_index as usize..run_end as usize].fill(temperature);
+
+                tile_index = run_end;
+            }
+            if tile_index != total_amount_of_tiles {
+                return Err(SaveError::Corrupt(format!("expected {} temperatures, found {}", total_amount_of_tiles, tile_index)));
+            }
+        }
+
+        let particle_count = reader.u32()?;
+        for _ in 0..particle_count {
+            let tile = reader.tile()?;
+            let position = Vector2::new(reader.f32()?, reader.f32()?);
+            let velocity = Vector2::new(reader.f32()?, reader.f32()?);
+
+            world.add_particle(Particle::new(tile, position, velocity));
+        }
+
+        if reader.version >= 8 {
+            world.set_boundaries(Boundaries {
+                left: reader.boundary_mode()?,
+                right: reader.boundary_mode()?,
+                top: reader.boundary_mode()?,
+                bottom: reader.boundary_mode()?
+            });
+        }
+
+        Ok(world)
+    }
+}
+
+fn run_length_encode(tiles: &[Tile]) -> Vec<(u32, Tile)> {
+    let mut runs: Vec<(u32, Tile)> = Vec::new();
+
+    for &tile in tiles {
+        // `last_frame_updated` only matters within a single tick, so it isn't saved
+        let tile = match tile {
+            Tile::LiveTile(live_tile) => Tile::LiveTile(LiveTile { last_frame_updated: 0, ..live_tile }),
+            tile => tile
+        };
+
+        match runs.last_mut() {
+            Some((run_length, run_tile)) if *run_tile == tile => *run_length += 1,
+            _ => runs.push((1, tile))
+        }
+    }
+
+    runs
+}
+
+fn run_length_encode_temperatures(temperatures: &[f32]) -> Vec<(u32, f32)> {
+    let mut runs: Vec<(u32, f32)> = Vec::new();
+
+    for &temperature in temperatures {
+        // Compare bits so the round trip is exact, even for -0.0
+        match runs.last_mut() {
+            Some((run_length, run_temperature)) if run_temperature.to_bits() == temperature.to_bits() => *run_length += 1,
+            _ => runs.push((1, temperature))
+        }
+    }
+
+    runs
+}
+
+struct SaveWriter<W: Write> {
+    writer: W
+}
+
+impl<W: Write> SaveWriter<W> {
+    fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)
+    }
+
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn f32(&mut self, value: f32) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn color(&mut self, color: Color) -> io::Result<()> {
+        self.bytes(&[color.r, color.g, color.b])
+    }
+
+    fn boundary_mode(&mut self, mode: BoundaryMode) -> io::Result<()> {
+        self.u8(match mode {
+            BoundaryMode::Wall => BOUNDARY_WALL,
+            BoundaryMode::Void => BOUNDARY_VOID,
+            BoundaryMode::Wrap => BOUNDARY_WRAP,
+            BoundaryMode::Mirror => BOUNDARY_MIRROR
+        })
+    }
+
+    fn tile(&mut self, tile: Tile) -> io::Result<()> {
+        match tile {
+            Tile::Empty => self.u8(TILE_EMPTY),
+            Tile::StaticTile(static_tile) => {
+                self.u8(TILE_STATIC)?;
+                self.u16(static_tile.material.0)?;
+                self.color(static_tile.color)
+            }
+            Tile::LiveTile(live_tile) => {
+                self.u8(TILE_LIVE)?;
+                self.u16(live_tile.material.0)?;
+                self.color(live_tile.color)?;
+                self.u16(live_tile.frames_since_color_change)?;
+                self.f32(live_tile.absorbed_heat)?;
+                self.u16(live_tile.ticks_left)?;
+                self.u16(live_tile.strength)
+            }
+        }
+    }
+}
+
+struct SaveReader<R: Read> {
+    reader: R,
+    version: u16,
+    /// Registry ids of the saved material table, in order, or the ids the registry lacks.
+    materials: Vec<Result<MaterialId, String>>
+}
+
+impl<R: Read> SaveReader<R> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveError> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<Vec<u8>, SaveError> {
+        // Read through `take` instead of allocating `length` up front, a corrupt length could be huge
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(length as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(SaveError::Truncated);
+        }
+
+        Ok(bytes)
+    }
+
+    fn string(&mut self, length: usize) -> Result<String, SaveError> {
+        String::from_utf8(self.bytes(length)?)
+            .map_err(|_| SaveError::Corrupt("material id isn't valid utf-8".to_string()))
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, SaveError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn color(&mut self) -> Result<Color, SaveError> {
+        let [r, g, b] = self.array()?;
+        Ok(Color::new(r, g, b))
+    }
+
+    fn material(&mut self) -> Result<MaterialId, SaveError> {
+        let index = self.u16()?;
+        self.table_material(index as usize)
+    }
+
+    fn table_material(&self, index: usize) -> Result<MaterialId, SaveError> {
+        match self.materials.get(index) {
+            Some(Ok(material)) => Ok(*material),
+            Some(Err(id)) => Err(SaveError::UnknownMaterial(id.clone())),
+            None => Err(SaveError::Corrupt(format!("unknown material index {}", index)))
+        }
+    }
+
+    fn boundary_mode(&mut self) -> Result<BoundaryMode, SaveError> {
+        match self.u8()? {
+            BOUNDARY_WALL => Ok(BoundaryMode::Wall),
+            BOUNDARY_VOID => Ok(BoundaryMode::Void),
+            BOUNDARY_WRAP => Ok(BoundaryMode::Wrap),
+            BOUNDARY_MIRROR => Ok(BoundaryMode::Mirror),
+            tag => Err(SaveError::Corrupt(format!("unknown boundary mode {}", tag)))
+        }
+    }
+
+    fn tile(&mut self) -> Result<Tile, SaveError> {
+        if self.version == 1 {
+            return self.version_1_tile();
+        }
+
+        let tile = match self.u8()? {
+            TILE_EMPTY => Tile::Empty,
+            TILE_STATIC => {
+                let material = self.material()?;
+                let color = self.color()?;
+
+                Tile::StaticTile(StaticTile::new(material, color))
+            }
+            TILE_LIVE => {
+                let material = self.material()?;
+                let color = self.color()?;
+                if self.version < 6 {
+                    // `under_water_ticks`, replaced by density based sinking
+                    self.u16()?;
+                }
+                let frames_since_color_change = self.u16()?;
+                let absorbed_heat = if self.version >= 4 { self.f32()? } else { 0.0 };
+                let ticks_left = if self.version >= 5 { self.u16()? } else { 0 };
+                let strength = if self.version >= 7 { self.u16()? } else { 0 };
+
+                Tile::LiveTile(LiveTile { frames_since_color_change, absorbed_heat, ticks_left, strength, ..LiveTile::new(material, color) })
+            }
+            tag => return Err(SaveError::Corrupt(format!("unknown tile tag {}", tag)))
+        };
+
+        Ok(tile)
+    }
+
+    /// Version 1 tiles, with `materials` holding stone, sand and water in that order.
+    fn version_1_tile(&mut self) -> Result<Tile, SaveError> {
+        let tile = match self.u8()? {
+            TILE_EMPTY => Tile::Empty,
+            VERSION_1_TILE_STATIC => Tile::StaticTile(StaticTile::new(self.table_material(0)?, self.color()?)),
+            VERSION_1_TILE_SAND => {
+                let color = self.color()?;
+                // `under_water_ticks`
+                self.u16()?;
+
+                Tile::LiveTile(LiveTile::new(self.table_material(1)?, color))
+            }
+            VERSION_1_TILE_WATER => {
+                let color = self.color()?;
+                let frames_since_color_change = self.u16()?;
+
+                Tile::LiveTile(LiveTile { frames_since_color_change, ..LiveTile::new(self.table_material(2)?, color) })
+            }
+            tag => return Err(SaveError::Corrupt(format!("unknown tile tag {}", tag)))
+        };
+
+        Ok(tile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temperature::AMBIENT_TEMPERATURE;
+    use rand::RngCore;
+
+    /// `WorldRng::seed_from_u64(0)` as `bincode` writes it.
+    const RNG_STATE: [u8; 16] = [190, 248, 229, 204, 117, 8, 71, 252, 135, 240, 70, 115, 6, 173, 108, 173];
+
+    fn load(bytes: &[u8]) -> Result<World, SaveError> {
+        World::load_from(bytes, MaterialRegistry::builtin())
+    }
+
+    fn header(version: u16, world_width: u32, world_height: u32) -> Vec<u8> {
+        [
+            &MAGIC[..],
+            &version.to_le_bytes(),
+            &world_width.to_le_bytes(),
+            &world_height.to_le_bytes(),
+            &7u64.to_le_bytes(),
+            &16u32.to_le_bytes(),
+            &RNG_STATE
+        ].concat()
+    }
+
+    /// An empty world in the layout of `version`, with a tile run `tiles` long.
+    fn empty_world(version: u16, world_width: u32, world_height: u32, tiles: u32) -> Vec<u8> {
+        let mut bytes = header(version, world_width, world_height);
+        if version >= 2 {
+            bytes.extend_from_slice(&0u16.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[1, 0, 0, 0]);
+        bytes.extend_from_slice(&tiles.to_le_bytes());
+        bytes.push(TILE_EMPTY);
+        if version >= 3 {
+            bytes.extend_from_slice(&[1, 0, 0, 0]);
+            bytes.extend_from_slice(&tiles.to_le_bytes());
+            bytes.extend_from_slice(&AMBIENT_TEMPERATURE.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        if version >= 8 {
+            bytes.extend_from_slice(&[BOUNDARY_WALL; 4]);
+        }
+
+        bytes
+    }
+
+    fn saved_world() -> (World, Vec<u8>) {
+        let mut world = World::with_seed(BACKGROUND_COLOR, 12, 8, 3);
+        let sand = world.materials().find("sand").unwrap();
+        let stone = world.materials().find("stone").unwrap();
+        let acid = world.materials().find("acid").unwrap();
+
+        for x in 0..12 {
+            let tile = world.create_tile(stone);
+            world.place_tile(Vector2::new(x, 7), tile);
+        }
+        let tile = world.create_tile(sand);
+        world.place_tile(Vector2::new(3, 6), tile);
+        let color = Color::new(1, 2, 3);
+        world.set_tile(Vector2::new(5, 6), Tile::LiveTile(LiveTile {
+            frames_since_color_change: 4,
+            absorbed_heat: -2.5,
+            ticks_left: 9,
+            strength: 11,
+            ..LiveTile::new(acid, color)
+        }));
+        world.set_temperature(Vector2::new(0, 0), -0.0);
+        world.set_temperature(Vector2::new(1, 0), 480.25);
+        world.add_particle(Particle::new(world.get_tile(Vector2::new(3, 6)), Vector2::new(2.5, 1.25), Vector2::new(-3.0, 4.0)));
+        world.set_boundaries(Boundaries { left: BoundaryMode::Wrap, right: BoundaryMode::Wrap, top: BoundaryMode::Void, bottom: BoundaryMode::Mirror });
+
+        let mut bytes = Vec::new();
+        world.save_to(&mut bytes).unwrap();
+
+        (world, bytes)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut world, bytes) = saved_world();
+        let mut loaded = load(&bytes).unwrap();
+
+        assert_eq!((loaded.world_width, loaded.world_height, loaded.seed()), (12, 8, 3));
+        assert!(loaded.tiles() == world.tiles());
+        assert_eq!(
+            loaded.temperatures().iter().map(|temperature| temperature.to_bits()).collect::<Vec<_>>(),
+            world.temperatures().iter().map(|temperature| temperature.to_bits()).collect::<Vec<_>>()
+        );
+        assert!(loaded.particles() == world.particles());
+        assert_eq!(loaded.boundaries(), world.boundaries());
+        assert_eq!(loaded.rng().next_u64(), world.rng().next_u64());
+    }
+
+    #[test]
+    fn every_version_loads() {
+        for version in 1..=FORMAT_VERSION {
+            let world = load(&empty_world(version, 3, 2, 6)).unwrap_or_else(|error| panic!("version {}: {}", version, error));
+
+            assert!(world.tiles().iter().all(|&tile| tile == Tile::Empty));
+            assert!(world.temperatures().iter().all(|&temperature| temperature == AMBIENT_TEMPERATURE));
+            assert_eq!(world.boundaries(), Boundaries::default());
+        }
+    }
+
+    #[test]
+    fn version_1_tiles() {
+        let bytes = [
+            &header(1, 3, 1)[..],
+            &[3, 0, 0, 0],
+            &[1, 0, 0, 0], &[VERSION_1_TILE_STATIC, 10, 20, 30],
+            // Sand with `under_water_ticks`
+            &[1, 0, 0, 0], &[VERSION_1_TILE_SAND, 40, 50, 60, 5, 0],
+            // Water with `frames_since_color_change`
+            &[1, 0, 0, 0], &[VERSION_1_TILE_WATER, 70, 80, 90, 9, 0],
+            &[0, 0, 0, 0]
+        ].concat();
+        let world = load(&bytes).unwrap();
+        let materials = world.materials().clone();
+
+        assert_eq!(world.seed(), 7);
+        assert_eq!(world.tiles(), &[
+            Tile::StaticTile(StaticTile::new(materials.find("stone").unwrap(), Color::new(10, 20, 30))),
+            Tile::LiveTile(LiveTile::new(materials.find("sand").unwrap(), Color::new(40, 50, 60))),
+            Tile::LiveTile(LiveTile { frames_since_color_change: 9, ..LiveTile::new(materials.find("water").unwrap(), Color::new(70, 80, 90)) })
+        ]);
+    }
+
+    #[test]
+    fn version_5_tiles() {
+        let bytes = [
+            &header(5, 2, 1)[..],
+            &[2, 0], &[4, 0], b"sand", &[5, 0], b"water",
+            &[2, 0, 0, 0],
+            // Water with `under_water_ticks`, frames since its color changed, absorbed heat and ticks left
+            &[1, 0, 0, 0], &[TILE_LIVE, 1, 0, 1, 2, 3, 3, 0, 4, 0], &1.5f32.to_le_bytes(), &[6, 0],
+            &[1, 0, 0, 0], &[TILE_EMPTY],
+            &[1, 0, 0, 0], &[2, 0, 0, 0], &100.0f32.to_le_bytes(),
+            &[1, 0, 0, 0], &[TILE_STATIC, 0, 0, 7, 7, 7], &0.5f32.to_le_bytes(), &0.25f32.to_le_bytes(), &[0; 8]
+        ].concat();
+        let world = load(&bytes).unwrap();
+        let materials = world.materials().clone();
+        let sand = materials.find("sand").unwrap();
+        let water = materials.find("water").unwrap();
+
+        assert_eq!(world.tiles(), &[
+            Tile::LiveTile(LiveTile { frames_since_color_change: 4, absorbed_heat: 1.5, ticks_left: 6, ..LiveTile::new(water, Color::new(1, 2, 3)) }),
+            Tile::Empty
+        ]);
+        assert_eq!(world.temperatures(), &[100.0, 100.0]);
+        assert_eq!(world.particles(), &[
+            Particle::new(Tile::StaticTile(StaticTile::new(sand, Color::new(7, 7, 7))), Vector2::new(0.5, 0.25), Vector2::new(0.0, 0.0))
+        ]);
+    }
+
+    #[test]
+    fn version_7_tiles() {
+        let bytes = [
+            &header(7, 1, 1)[..],
+            &[1, 0], &[4, 0], b"acid",
+            &[1, 0, 0, 0],
+            &[1, 0, 0, 0], &[TILE_LIVE, 0, 0, 1, 2, 3, 0, 0], &[0; 4], &[0, 0], &[12, 0],
+            &[1, 0, 0, 0], &[1, 0, 0, 0], &AMBIENT_TEMPERATURE.to_le_bytes(),
+            &[0, 0, 0, 0]
+        ].concat();
+        let world = load(&bytes).unwrap();
+        let acid = world.materials().find("acid").unwrap();
+
+        assert_eq!(world.tiles(), &[Tile::LiveTile(LiveTile { strength: 12, ..LiveTile::new(acid, Color::new(1, 2, 3)) })]);
+        assert_eq!(world.boundaries(), Boundaries::default());
+    }
+
+    #[test]
+    fn unused_materials_may_be_missing() {
+        let materials = Arc::new(MaterialRegistry::from_ron_str(r#"(
+            materials: [
+                (id: "sand", name: "Sand", colors: [(208, 191, 146)], density: 1.6, behavior: Powder, brush: Fill(radius: 1)),
+            ],
+            reactions: [],
+        )"#).unwrap());
+
+        let mut world = World::with_seed(BACKGROUND_COLOR, 4, 4, 0);
+        let sand = world.materials().find("sand").unwrap();
+        let tile = world.create_tile(sand);
+        world.place_tile(Vector2::new(1, 3), tile);
+
+        let mut bytes = Vec::new();
+        world.save_to(&mut bytes).unwrap();
+        let loaded = World::load_from(&bytes[..], materials.clone()).unwrap();
+        assert_eq!(loaded.get_tile(Vector2::new(1, 3)).get_material(), materials.find("sand"));
+
+        let water = world.materials().find("water").unwrap();
+        let tile = world.create_tile(water);
+        world.place_tile(Vector2::new(2, 3), tile);
+
+        let mut bytes = Vec::new();
+        world.save_to(&mut bytes).unwrap();
+        assert!(matches!(World::load_from(&bytes[..], materials), Err(SaveError::UnknownMaterial(id)) if id == "water"));
+    }
+
+    #[test]
+    fn not_a_save_file() {
+        assert!(matches!(load(b""), Err(SaveError::NotASaveFile)));
+        assert!(matches!(load(b"PO"), Err(SaveError::NotASaveFile)));
+        assert!(matches!(load(b"\x89PNG\r\n\x1a\n"), Err(SaveError::NotASaveFile)));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert!(matches!(load(&empty_world(0, 1, 1, 1)), Err(SaveError::UnsupportedVersion(0))));
+
+        let version = FORMAT_VERSION + 1;
+        assert!(matches!(load(&empty_world(version, 1, 1, 1)), Err(SaveError::UnsupportedVersion(v)) if v == version));
+    }
+
+    #[test]
+    fn truncated() {
+        let (_, bytes) = saved_world();
+
+        for length in MAGIC.len()..bytes.len() {
+            assert!(matches!(load(&bytes[..length]), Err(SaveError::Truncated)), "cut off after {} bytes", length);
+        }
+    }
+
+    #[test]
+    fn corrupt() {
+        let corrupt = |bytes: &[u8]| matches!(load(bytes), Err(SaveError::Corrupt(_)));
+
+        // Sizes
+        assert!(corrupt(&empty_world(FORMAT_VERSION, 0, 1, 0)));
+        assert!(corrupt(&empty_world(FORMAT_VERSION, MAX_WORLD_SIZE + 1, 1, MAX_WORLD_SIZE + 1)));
+
+        // Tile runs that don't add up to the world size
+        assert!(corrupt(&empty_world(FORMAT_VERSION, 2, 2, 3)));
+        assert!(corrupt(&empty_world(FORMAT_VERSION, 2, 2, 5)));
+        assert!(corrupt(&empty_world(1, 2, 2, 5)));
+
+        // Temperature runs that don't add up to the world size
+        let mut bytes = empty_world(FORMAT_VERSION, 2, 2, 4);
+        let temperature_run = header(FORMAT_VERSION, 2, 2).len() + 2 + 4 + 4 + 1 + 4;
+        bytes[temperature_run..temperature_run + 4].copy_from_slice(&3u32.to_le_bytes());
+        assert!(corrupt(&bytes));
+
+        let tile_run = |tile: &[u8]| [&header(FORMAT_VERSION, 1, 1)[..], &[1, 0], &[4, 0], b"sand", &[1, 0, 0, 0], &[1, 0, 0, 0], tile].concat();
+        assert!(corrupt(&tile_run(&[9])));
+        assert!(corrupt(&tile_run(&[TILE_STATIC, 1, 0, 0, 0, 0])));
+
+        let mut bytes = empty_world(FORMAT_VERSION, 1, 1, 1);
+        *bytes.last_mut().unwrap() = 9;
+        assert!(corrupt(&bytes));
+    }
+}
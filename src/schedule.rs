@@ -0,0 +1,388 @@
+//! Updates the tiles of awake chunks, spread over threads.
+//!
+//! A tick updates the chunks in four phases, each taking every other chunk in both directions,
+//! like the squares of one color on a checkerboard. Every chunk update works on its own copy of
+//! the cells within `MAX_MOVE` of the chunk's dirty rectangle, which is written back once its
+//! phase is done, and looks at the rest of the world as it was when the phase started. Chunks
+//! updated in the same phase are a whole chunk apart, so as long as tiles only look at cells
+//! within `MAX_REACH` and only move or react with cells within `MAX_MOVE`, no chunk update sees
+//! something another one changes.
+//!
+//! Each chunk update draws from its own RNG, seeded from the world's RNG once per tick, so a
+//! world evolves the same whether its chunks are updated on one thread or many.
+
+use crate::world::{World, WorldRng};
+use crate::tile::{Tile, LiveTile, LiveTileApi, LiveTileInstruction};
+use crate::command::TileCommand;
+use crate::chunk::{DirtyRect, CHUNK_SIZE};
+use crate::boundary::{Boundaries, Resolved};
+use cgmath::{Vector2, ElementWise};
+use rand::{Rng, SeedableRng};
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
+
+/// Furthest away from itself, in tiles, a tile can look at other tiles, e.g. the radius of an explosion.
+pub const MAX_REACH: u32 = CHUNK_SIZE / 2;
+
+/// Furthest away from itself a tile can directly change other tiles, by moving or reacting.
+/// Changes further away go through `TileCommand`s.
+const MAX_MOVE: u32 = 2;
+
+/// The order a chunk update visits the cells of its dirty rectangle in.
+///
+/// Tiles that move into a cell that is yet to be visited don't get updated twice, so whichever
+/// cells go first get a head start. Visiting the cells in the same order every tick makes
+/// tiles favour one direction, which the alternating and random orders avoid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ScanOrder {
+    /// Column by column from left to right, each from top to bottom.
+    Columns,
+    /// Row by row from the bottom up, each from left to right.
+    BottomUp,
+    /// Row by row from the bottom up, switching between left to right and right to left every
+    /// row and every tick.
+    #[default]
+    Alternating,
+    /// A new random order every tick.
+    Random
+}
+
+/// A copy of the cells a chunk update can change.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub origin: Vector2<u32>,
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<Tile>,
+    pub temperatures: Vec<f32>
+}
+
+impl Region {
+    /// A region without any cells, for looking at the world without changing it.
+    pub fn empty() -> Region {
+        Region {
+            origin: Vector2::new(0, 0),
+            width: 0,
+            height: 0,
+            tiles: Vec::new(),
+            temperatures: Vec::new()
+        }
+    }
+
+    /// Copies the cells from `min` to `max`, both included.
+    fn copy(world: &World, min: Vector2<u32>, max: Vector2<u32>) -> Region {
+        let width = max.x - min.x + 1;
+        let height = max.y - min.y + 1;
+
+        let mut tiles = Vec::with_capacity((width * height) as usize);
+        let mut temperatures = Vec::with_capacity((width * height) as usize);
+        for y in min.y..=max.y {
+            let row = (min.x + y * world.world_width) as usize..=(max.x + y * world.world_width) as usize;
+            tiles.extend_from_slice(&world.tiles[row.clone()]);
+            temperatures.extend_from_slice(&world.temperatures[row]);
+        }
+
+        Region { origin: min, width, height, tiles, temperatures }
+    }
+
+    /// Index of the cell at `tile_position` in `tiles` and `temperatures`, `None` outside the region.
+    pub fn get_index(&self, tile_position: Vector2<u32>) -> Option<usize> {
+        let x = tile_position.x.checked_sub(self.origin.x).filter(|&x| x < self.width)?;
+        let y = tile_position.y.checked_sub(self.origin.y).filter(|&y| y < self.height)?;
+
+        Some((x + y * self.width) as usize)
+    }
+}
+
+/// The update of one chunk, which only changes its own copy of the cells within `MAX_MOVE` of
+/// the chunk's dirty rectangle.
+pub(crate) struct ChunkUpdate {
+    dirty_rect: DirtyRect,
+    region: Region,
+    world_width: u32,
+    world_height: u32,
+    boundaries: Boundaries,
+    /// Cells whose tiles changed, to wake them afterwards.
+    changed_cells: Vec<Vector2<u32>>,
+    commands: Vec<TileCommand>,
+    rng: WorldRng
+}
+
+impl ChunkUpdate {
+    fn new(world: &World, chunk_position: Vector2<u32>, dirty_rect: DirtyRect, tick_seed: u64) -> ChunkUpdate {
+        let min = Vector2::new(dirty_rect.min.x.saturating_sub(MAX_MOVE), dirty_rect.min.y.saturating_sub(MAX_MOVE));
+        let max = Vector2::new(
+            (dirty_rect.max.x + MAX_MOVE).min(world.world_width - 1),
+            (dirty_rect.max.y + MAX_MOVE).min(world.world_height - 1)
+        );
+
+        ChunkUpdate {
+            dirty_rect,
+            region: Region::copy(world, min, max),
+            world_width: world.world_width,
+            world_height: world.world_height,
+            boundaries: world.boundaries,
+            changed_cells: Vec::new(),
+            commands: Vec::new(),
+            rng: WorldRng::seed_from_u64(tick_seed ^ (chunk_position.x as u64) ^ ((chunk_position.y as u64) << 32))
+        }
+    }
+
+    fn get_tile_index(&self, tile_position: Vector2<u32>) -> usize {
+        self.region.get_index(tile_position).expect("tile changed outside of its chunk update's region")
+    }
+
+    pub(crate) fn world_width(&self) -> u32 {
+        self.world_width
+    }
+
+    pub(crate) fn world_height(&self) -> u32 {
+        self.world_height
+    }
+
+    pub(crate) fn rng(&mut self) -> &mut WorldRng {
+        &mut self.rng
+    }
+
+    pub(crate) fn get_tile(&self, tile_position: Vector2<u32>) -> Tile {
+        self.region.tiles[self.get_tile_index(tile_position)]
+    }
+
+    pub(crate) fn set_tile(&mut self, tile_position: Vector2<u32>, tile: Tile) {
+        let tile_index = self.get_tile_index(tile_position);
+        self.region.tiles[tile_index] = tile;
+        self.changed_cells.push(tile_position);
+    }
+
+    /// Keeps the cell at `tile_position` awake, see `World::wake`.
+    pub(crate) fn wake(&mut self, tile_position: Vector2<u32>) {
+        self.changed_cells.push(tile_position);
+    }
+
+    pub(crate) fn push_command(&mut self, command: TileCommand) {
+        self.commands.push(command);
+    }
+
+    fn run(&mut self, world: &World, current_frame: u64) {
+        let DirtyRect { min, max } = self.dirty_rect;
+
+        match world.scan_order {
+            ScanOrder::Columns => {
+                for x in min.x..=max.x {
+                    for y in min.y..=max.y {
+                        self.update_tile(Vector2::new(x, y), world, current_frame);
+                    }
+                }
+            }
+            ScanOrder::BottomUp => {
+                for y in (min.y..=max.y).rev() {
+                    for x in min.x..=max.x {
+                        self.update_tile(Vector2::new(x, y), world, current_frame);
+                    }
+                }
+            }
+            ScanOrder::Alternating => {
+                for y in (min.y..=max.y).rev() {
+                    if (u64::from(y) + current_frame).is_multiple_of(2) {
+                        for x in min.x..=max.x {
+                            self.update_tile(Vector2::new(x, y), world, current_frame);
+                        }
+                    } else {
+                        for x in (min.x..=max.x).rev() {
+                            self.update_tile(Vector2::new(x, y), world, current_frame);
+                        }
+                    }
+                }
+            }
+            ScanOrder::Random => {
+                let mut cells: Vec<Vector2<u32>> = (min.y..=max.y)
+                    .flat_map(|y| (min.x..=max.x).map(move |x| Vector2::new(x, y)))
+                    .collect();
+                cells.shuffle(&mut self.rng);
+
+                for tile_position in cells {
+                    self.update_tile(tile_position, world, current_frame);
+                }
+            }
+        }
+    }
+
+    fn update_tile(&mut self, tile_position: Vector2<u32>, world: &World, current_frame: u64) {
+        let materials = &world.materials;
+        let tile_index = self.get_tile_index(tile_position);
+
+        let mut live_tile = match self.region.tiles[tile_index] {
+            Tile::LiveTile(live_tile) => live_tile,
+            _ => return
+        };
+
+        if live_tile.last_frame_updated == current_frame {
+            return;
+        }
+
+        if self.react(tile_position, live_tile.material, materials, current_frame) {
+            return;
+        }
+
+        let tile_before_update = live_tile;
+        let live_tile_instruction = live_tile.update(LiveTileApi {
+            tile_position,
+            tiles: &world.tiles,
+            temperatures: &world.temperatures,
+            region: &mut self.region,
+            world_width: self.world_width,
+            world_height: self.world_height,
+            boundaries: self.boundaries,
+            materials,
+            commands: &mut self.commands,
+            rng: &mut self.rng
+        });
+
+        match live_tile_instruction {
+            LiveTileInstruction::None => {
+                self.region.tiles[tile_index] = Tile::LiveTile(live_tile);
+
+                // Counting towards the next color alone doesn't keep a tile awake
+                let live_tile = LiveTile { frames_since_color_change: tile_before_update.frames_since_color_change, ..live_tile };
+                if live_tile != tile_before_update {
+                    self.wake(tile_position);
+                }
+            },
+            LiveTileInstruction::Replace(tile_offset) => {
+                live_tile.last_frame_updated = current_frame;
+                self.move_tile(tile_position, live_tile, tile_offset, false, world);
+            }
+            LiveTileInstruction::Switch(tile_offset) => {
+                live_tile.last_frame_updated = current_frame;
+                self.move_tile(tile_position, live_tile, tile_offset, true, world);
+            }
+            LiveTileInstruction::ReplaceSelfWith(replacement_tile) => {
+                self.set_tile(tile_position, replacement_tile);
+            }
+        }
+    }
+
+    /// Moves `live_tile` from `tile_position` to the cell `tile_offset` leads to. With `switch`
+    /// the tile there takes its place, otherwise it's replaced.
+    fn move_tile(&mut self, tile_position: Vector2<u32>, live_tile: LiveTile, tile_offset: Vector2<i32>, switch: bool, world: &World) {
+        let tile_index = self.get_tile_index(tile_position);
+        let target = tile_offset.add_element_wise(tile_position.cast().unwrap());
+
+        let new_tile_position = match self.boundaries.resolve(target, self.world_width, self.world_height) {
+            Resolved::Inside(new_tile_position) => new_tile_position,
+            Resolved::Wall => {
+                self.region.tiles[tile_index] = Tile::LiveTile(live_tile);
+                return;
+            }
+            // Whatever a tile switches with in a void is empty too
+            Resolved::Void => {
+                self.set_tile(tile_position, Tile::Empty);
+                return;
+            }
+        };
+
+        let new_tile_index = match self.region.get_index(new_tile_position) {
+            Some(new_tile_index) => new_tile_index,
+            None => {
+                // Wrapped around to the far side of the world, which another chunk update may be
+                // changing, so the move has to wait for the end of the tick
+                self.region.tiles[tile_index] = Tile::LiveTile(live_tile);
+                self.push_command(TileCommand::MoveTile {
+                    from: tile_position,
+                    to: new_tile_position,
+                    material: live_tile.material,
+                    expected: world.get_tile(new_tile_position).get_material(),
+                    switch
+                });
+                return;
+            }
+        };
+
+        let other_tile = if switch { self.region.tiles[new_tile_index] } else { Tile::Empty };
+
+        // Heat travels with the tile
+        self.region.temperatures.swap(tile_index, new_tile_index);
+        self.set_tile(tile_position, other_tile);
+        self.set_tile(new_tile_position, Tile::LiveTile(live_tile))
+    }
+}
+
+impl World {
+    /// Whether `World::update` spreads the chunks over threads. The outcome is the same either way.
+    pub fn is_multithreaded(&self) -> bool {
+        self.multithreaded
+    }
+
+    pub fn set_multithreaded(&mut self, multithreaded: bool) {
+        self.multithreaded = multithreaded;
+    }
+
+    /// The order `World::update` visits the cells of each chunk in.
+    pub fn scan_order(&self) -> ScanOrder {
+        self.scan_order
+    }
+
+    pub fn set_scan_order(&mut self, scan_order: ScanOrder) {
+        self.scan_order = scan_order;
+    }
+
+    /// Runs one tick of tile updates over the awake chunks.
+    pub(crate) fn update_chunks(&mut self, current_frame: u64) {
+        let tick_seed: u64 = self.rng.gen();
+        let dirty_rects = self.chunks.begin_tick();
+
+        // Which of two neighbouring chunks goes first switches every tick sideways and every
+        // other tick up and down, so neither gets a head start
+        let flip = Vector2::new((current_frame % 2) as u32, (current_frame / 2 % 2) as u32);
+
+        for phase in 0..4 {
+            let phase_rects: Vec<(Vector2<u32>, DirtyRect)> = dirty_rects.iter()
+                .copied()
+                .filter(|(chunk_position, _)| ((chunk_position.x % 2) ^ flip.x) + ((chunk_position.y % 2) ^ flip.y) * 2 == phase)
+                .collect();
+
+            let world = &*self;
+            let update_chunk = |&(chunk_position, dirty_rect): &(Vector2<u32>, DirtyRect)| {
+                let mut update = ChunkUpdate::new(world, chunk_position, dirty_rect, tick_seed);
+                update.run(world, current_frame);
+                update
+            };
+
+            let updates: Vec<ChunkUpdate> = if self.multithreaded {
+                phase_rects.par_iter().map(update_chunk).collect()
+            } else {
+                phase_rects.iter().map(update_chunk).collect()
+            };
+
+            for update in updates {
+                self.write_back(update);
+            }
+        }
+    }
+
+    /// Copies the cells a chunk update can have changed back into the world.
+    fn write_back(&mut self, mut update: ChunkUpdate) {
+        let region = &update.region;
+
+        for y in 0..region.height {
+            let region_row = (y * region.width) as usize..((y + 1) * region.width) as usize;
+            let world_start = (region.origin.x + (region.origin.y + y) * self.world_width) as usize;
+            let world_row = world_start..world_start + region.width as usize;
+
+            self.temperatures[world_row.clone()].copy_from_slice(&region.temperatures[region_row.clone()]);
+
+            // Only redraw what changed
+            for (world_index, &tile) in world_row.zip(&region.tiles[region_row]) {
+                if self.tiles[world_index] != tile {
+                    self.write_tile(Vector2::new(world_index as u32 % self.world_width, world_index as u32 / self.world_width), tile);
+                }
+            }
+        }
+
+        for tile_position in update.changed_cells {
+            self.chunks.mark(tile_position);
+        }
+
+        self.commands.append(&mut update.commands);
+    }
+}
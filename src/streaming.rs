@@ -0,0 +1,358 @@
+//! An unbounded world made of chunks, of which only the ones around a camera are simulated.
+//!
+//! A `StreamingWorld` keeps a regular `World` over the chunks around its camera, so tiles look
+//! across chunk borders just like anywhere else in a world. The outermost ring of chunks of that
+//! world is frozen: its tiles can be looked at and pushed into by their neighbours but don't
+//! update themselves, so nothing spills into chunks that aren't loaded. When the camera moves on,
+//! the simulated area follows it.
+//!
+//! Chunks that fall behind are kept in memory for a while and then written to a directory, one
+//! save file per chunk, to be read back once the camera returns. Chunks that were never visited
+//! are created empty. Particles are only simulated within the area and are dropped when it
+//! moves away from them.
+
+use crate::BACKGROUND_COLOR;
+use crate::world::World;
+use crate::tile::Tile;
+use crate::material::MaterialRegistry;
+use crate::temperature::AMBIENT_TEMPERATURE;
+use crate::chunk::CHUNK_SIZE;
+use crate::save::SaveError;
+use cgmath::Vector2;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How far, in chunks, the camera can get from the middle of the simulated area before the area
+/// moves along with it.
+const RECENTER_DISTANCE: i64 = 1;
+
+/// Chunks further than this from the simulated area, in chunks, are written to disk.
+const RESIDENT_DISTANCE: i64 = 2;
+
+/// The tiles and temperatures of a chunk outside the simulated area, row by row.
+#[derive(Clone, Debug)]
+struct StoredChunk {
+    tiles: Vec<Tile>,
+    temperatures: Vec<f32>
+}
+
+impl StoredChunk {
+    fn empty() -> StoredChunk {
+        let amount_of_tiles = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+        StoredChunk {
+            tiles: vec![Tile::Empty; amount_of_tiles],
+            temperatures: vec![AMBIENT_TEMPERATURE; amount_of_tiles]
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tiles.iter().all(|&tile| tile == Tile::Empty)
+            && self.temperatures.iter().all(|&temperature| temperature == AMBIENT_TEMPERATURE)
+    }
+}
+
+pub struct StreamingWorld {
+    /// The simulated area.
+    world: World,
+    /// Position of the simulated area's top left chunk, in chunks.
+    origin: Vector2<i64>,
+    /// Size of the simulated area, in chunks.
+    size: Vector2<u32>,
+    /// Chunks outside the simulated area that haven't been written to disk yet.
+    resident: HashMap<Vector2<i64>, StoredChunk>,
+    directory: PathBuf,
+    materials: Arc<MaterialRegistry>
+}
+
+impl StreamingWorld {
+    /// Creates a world whose simulated area is `size` chunks large and centered on the origin.
+    /// Chunks are paged to `directory`, chunks already saved there by an earlier run are picked
+    /// up again.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `size` is less than 3 chunks in either direction, which leaves no chunks
+    /// within the frozen ring.
+    pub fn new<P: Into<PathBuf>>(directory: P, size: Vector2<u32>, seed: u64, materials: Arc<MaterialRegistry>) -> Result<StreamingWorld, SaveError> {
+        assert!(size.x >= 3 && size.y >= 3, "a streaming world needs to simulate at least 3x3 chunks");
+
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        let world = World::with_materials(BACKGROUND_COLOR, size.x * CHUNK_SIZE, size.y * CHUNK_SIZE, seed, materials.clone());
+        let mut streaming_world = StreamingWorld {
+            world,
+            origin: Vector2::new(-i64::from(size.x / 2), -i64::from(size.y / 2)),
+            size,
+            resident: HashMap::new(),
+            directory,
+            materials
+        };
+        streaming_world.load_area()?;
+
+        Ok(streaming_world)
+    }
+
+    /// The simulated area. Its tile positions are relative to `StreamingWorld::origin`.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Position of the simulated area's top left tile.
+    pub fn origin(&self) -> Vector2<i64> {
+        self.origin * i64::from(CHUNK_SIZE)
+    }
+
+    /// The position within the simulated area of the tile at `position`, if it is in there.
+    pub fn to_area(&self, position: Vector2<i64>) -> Option<Vector2<u32>> {
+        let area_position = position - self.origin();
+
+        if area_position.x < 0 || area_position.y < 0 || area_position.x >= i64::from(self.world.world_width) || area_position.y >= i64::from(self.world.world_height) {
+            return None;
+        }
+
+        Some(Vector2::new(area_position.x as u32, area_position.y as u32))
+    }
+
+    /// The tile at `position`, `None` if its chunk is on disk.
+    pub fn get_tile(&self, position: Vector2<i64>) -> Option<Tile> {
+        if let Some(area_position) = self.to_area(position) {
+            return Some(self.world.get_tile(area_position));
+        }
+
+        let (chunk_position, index) = split_position(position);
+        match self.resident.get(&chunk_position) {
+            Some(chunk) => Some(chunk.tiles[index]),
+            None if self.chunk_path(chunk_position).exists() => None,
+            None => Some(Tile::Empty)
+        }
+    }
+
+    /// Simulates the area around the camera for a tick, see `World::update`.
+    pub fn update(&mut self, delta_time: f32, current_frame: u64) {
+        self.world.update(delta_time, current_frame);
+    }
+
+    /// Moves the simulated area along if `camera`, a tile position, got too far from its middle.
+    pub fn set_camera(&mut self, camera: Vector2<i64>) -> Result<(), SaveError> {
+        let chunk_size = i64::from(CHUNK_SIZE);
+        let camera_chunk = Vector2::new(camera.x.div_euclid(chunk_size), camera.y.div_euclid(chunk_size));
+        let center = self.origin + Vector2::new(i64::from(self.size.x / 2), i64::from(self.size.y / 2));
+
+        if (camera_chunk.x - center.x).abs() <= RECENTER_DISTANCE && (camera_chunk.y - center.y).abs() <= RECENTER_DISTANCE {
+            return Ok(());
+        }
+
+        let new_origin = camera_chunk - Vector2::new(i64::from(self.size.x / 2), i64::from(self.size.y / 2));
+        self.move_area(new_origin)
+    }
+
+    /// Writes every chunk to disk, e.g. before quitting. The chunks stay loaded.
+    pub fn flush(&mut self) -> Result<(), SaveError> {
+        for chunk_y in 0..self.size.y {
+            for chunk_x in 0..self.size.x {
+                let chunk = self.extract_chunk(Vector2::new(chunk_x, chunk_y));
+                self.write_chunk(self.origin + Vector2::new(i64::from(chunk_x), i64::from(chunk_y)), &chunk)?;
+            }
+        }
+
+        for (&chunk_position, chunk) in &self.resident {
+            self.write_chunk(chunk_position, chunk)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the simulated area so its top left chunk is at `new_origin`. Nothing changes until
+    /// every chunk that needs to be written or read was, so a failing disk leaves the world as
+    /// it was.
+    fn move_area(&mut self, new_origin: Vector2<i64>) -> Result<(), SaveError> {
+        // Everything in the area becomes a stored chunk, kept apart until the move succeeded
+        let mut leaving = HashMap::new();
+        for chunk_y in 0..self.size.y {
+            for chunk_x in 0..self.size.x {
+                let chunk = self.extract_chunk(Vector2::new(chunk_x, chunk_y));
+                leaving.insert(self.origin + Vector2::new(i64::from(chunk_x), i64::from(chunk_y)), chunk);
+            }
+        }
+
+        let far_away: Vec<Vector2<i64>> = self.resident.keys()
+            .chain(leaving.keys())
+            .copied()
+            .filter(|&chunk_position| distance_to_area(new_origin, self.size, chunk_position) > RESIDENT_DISTANCE)
+            .collect();
+        for &chunk_position in &far_away {
+            let chunk = leaving.get(&chunk_position).or_else(|| self.resident.get(&chunk_position)).unwrap();
+            self.write_chunk(chunk_position, chunk)?;
+        }
+
+        let chunks = self.gather_area(new_origin, &leaving)?;
+
+        // A fresh world keeps the settings and random number generator of the old one
+        let mut world = World::with_materials(BACKGROUND_COLOR, self.world.world_width, self.world.world_height, self.world.seed(), self.materials.clone());
+        world.rng = self.world.rng.clone();
+        world.set_boundaries(self.world.boundaries());
+        world.set_multithreaded(self.world.is_multithreaded());
+        world.set_scan_order(self.world.scan_order());
+        fill_area(&mut world, self.size, &chunks);
+
+        let shift = (self.origin - new_origin) * i64::from(CHUNK_SIZE);
+        let world_size = Vector2::new(world.world_width as f32, world.world_height as f32);
+        for mut particle in std::mem::take(&mut self.world.particles) {
+            particle.position += Vector2::new(shift.x as f32, shift.y as f32);
+
+            if particle.position.x >= 0.0 && particle.position.y >= 0.0 && particle.position.x < world_size.x && particle.position.y < world_size.y {
+                world.add_particle(particle);
+            }
+        }
+
+        self.world = world;
+        self.origin = new_origin;
+        self.resident.extend(leaving);
+        for chunk_position in far_away {
+            self.resident.remove(&chunk_position);
+        }
+        for chunk_y in 0..i64::from(self.size.y) {
+            for chunk_x in 0..i64::from(self.size.x) {
+                self.resident.remove(&(new_origin + Vector2::new(chunk_x, chunk_y)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fills the simulated area with its chunks, from memory, from disk or empty ones.
+    fn load_area(&mut self) -> Result<(), SaveError> {
+        let chunks = self.gather_area(self.origin, &HashMap::new())?;
+        fill_area(&mut self.world, self.size, &chunks);
+
+        for chunk_y in 0..i64::from(self.size.y) {
+            for chunk_x in 0..i64::from(self.size.x) {
+                self.resident.remove(&(self.origin + Vector2::new(chunk_x, chunk_y)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The chunks of an area at `origin`, row by row, looked up in `leaving`, then in memory and
+    /// then on disk.
+    fn gather_area(&self, origin: Vector2<i64>, leaving: &HashMap<Vector2<i64>, StoredChunk>) -> Result<Vec<StoredChunk>, SaveError> {
+        let mut chunks = Vec::with_capacity((self.size.x * self.size.y) as usize);
+
+        for chunk_y in 0..self.size.y {
+            for chunk_x in 0..self.size.x {
+                let chunk_position = origin + Vector2::new(i64::from(chunk_x), i64::from(chunk_y));
+                let chunk = match leaving.get(&chunk_position).or_else(|| self.resident.get(&chunk_position)) {
+                    Some(chunk) => chunk.clone(),
+                    None => self.read_chunk(chunk_position)?
+                };
+
+                chunks.push(chunk);
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Copies the chunk at `chunk_position` within the simulated area out of it.
+    fn extract_chunk(&self, chunk_position: Vector2<u32>) -> StoredChunk {
+        let mut chunk = StoredChunk::empty();
+        let world_width = self.world.world_width;
+
+        for y in 0..CHUNK_SIZE {
+            let start = (chunk_position.x * CHUNK_SIZE + (chunk_position.y * CHUNK_SIZE + y) * world_width) as usize;
+            let row = (y * CHUNK_SIZE) as usize..((y + 1) * CHUNK_SIZE) as usize;
+
+            chunk.tiles[row.clone()].copy_from_slice(&self.world.tiles[start..start + CHUNK_SIZE as usize]);
+            chunk.temperatures[row].copy_from_slice(&self.world.temperatures[start..start + CHUNK_SIZE as usize]);
+        }
+
+        chunk
+    }
+
+    fn chunk_path(&self, chunk_position: Vector2<i64>) -> PathBuf {
+        self.directory.join(format!("chunk_{}_{}.powpowder", chunk_position.x, chunk_position.y))
+    }
+
+    /// Saves a chunk as a world of its own. Empty chunks aren't stored at all.
+    fn write_chunk(&self, chunk_position: Vector2<i64>, chunk: &StoredChunk) -> Result<(), SaveError> {
+        let path = self.chunk_path(chunk_position);
+
+        if chunk.is_empty() {
+            return match fs::remove_file(&path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+                _ => Ok(())
+            };
+        }
+
+        let mut world = World::with_materials(BACKGROUND_COLOR, CHUNK_SIZE, CHUNK_SIZE, 0, self.materials.clone());
+        world.tiles.copy_from_slice(&chunk.tiles);
+        world.temperatures.copy_from_slice(&chunk.temperatures);
+
+        world.save_to(BufWriter::new(File::create(path)?))
+    }
+
+    fn read_chunk(&self, chunk_position: Vector2<i64>) -> Result<StoredChunk, SaveError> {
+        let file = match File::open(self.chunk_path(chunk_position)) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(StoredChunk::empty()),
+            Err(error) => return Err(error.into())
+        };
+
+        let world = World::load_from(BufReader::new(file), self.materials.clone())?;
+        if world.world_width != CHUNK_SIZE || world.world_height != CHUNK_SIZE {
+            return Err(SaveError::Corrupt(format!("chunk {}, {} isn't {} tiles large", chunk_position.x, chunk_position.y, CHUNK_SIZE)));
+        }
+
+        Ok(StoredChunk { tiles: world.tiles, temperatures: world.temperatures })
+    }
+}
+
+/// The position of the chunk `position` lies in, in chunks, and its index within the chunk.
+fn split_position(position: Vector2<i64>) -> (Vector2<i64>, usize) {
+    let chunk_size = i64::from(CHUNK_SIZE);
+    let chunk_position = Vector2::new(position.x.div_euclid(chunk_size), position.y.div_euclid(chunk_size));
+    let index = position.x.rem_euclid(chunk_size) + position.y.rem_euclid(chunk_size) * chunk_size;
+
+    (chunk_position, index as usize)
+}
+
+/// How many chunks `chunk_position` lies away from an area `size` chunks large at `origin`.
+fn distance_to_area(origin: Vector2<i64>, size: Vector2<u32>, chunk_position: Vector2<i64>) -> i64 {
+    let max = origin + Vector2::new(i64::from(size.x), i64::from(size.y)) - Vector2::new(1, 1);
+    let distance_x = (origin.x - chunk_position.x).max(chunk_position.x - max.x).max(0);
+    let distance_y = (origin.y - chunk_position.y).max(chunk_position.y - max.y).max(0);
+
+    distance_x.max(distance_y)
+}
+
+/// Writes `chunks`, row by row, into `world`, an area `size` chunks large.
+fn fill_area(world: &mut World, size: Vector2<u32>, chunks: &[StoredChunk]) {
+    for chunk_y in 0..size.y {
+        for chunk_x in 0..size.x {
+            let chunk = &chunks[(chunk_x + chunk_y * size.x) as usize];
+
+            let min = Vector2::new(chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE);
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let index = (x + y * CHUNK_SIZE) as usize;
+                    let tile_position = Vector2::new(min.x + x, min.y + y);
+
+                    world.write_tile(tile_position, chunk.tiles[index]);
+                    world.set_temperature(tile_position, chunk.temperatures[index]);
+                }
+            }
+        }
+    }
+
+    world.chunks.set_frozen_margin(1);
+}
@@ -0,0 +1,339 @@
+//! Heat stored per tile and spread between neighbouring tiles.
+//!
+//! Every cell of the world, empty or not, has a temperature in °C. Each tick heat flows across
+//! the edges between neighbouring cells at a rate set by the conductivity of the slower of the two
+//! materials, and changes their temperatures in inverse proportion to their heat capacities.
+//! Heat moves along with tiles and the edge of the world is insulated, but empty cells slowly
+//! return to the ambient temperature, as if the air carried heat away. Without that a world
+//! would keep all the heat its lava or fire ever gave off.
+//!
+//! Heat only diffuses within chunks whose tiles are awake or whose temperatures are still
+//! changing noticeably, so a world that has cooled down costs next to nothing.
+
+use crate::Color;
+use crate::world::World;
+use crate::tile::Tile;
+use crate::material::MaterialRegistry;
+use crate::chunk::CHUNK_SIZE;
+use cgmath::Vector2;
+use rayon::prelude::*;
+
+/// Temperature of new worlds, in °C.
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+/// Conductivity of empty cells, air is a poor conductor.
+pub const AIR_CONDUCTIVITY: f32 = 0.05;
+
+/// Heat capacity of empty cells.
+pub const AIR_HEAT_CAPACITY: f32 = 0.1;
+
+/// Fraction of their difference to the ambient temperature empty cells lose per tick.
+pub const AIR_COOLING_RATE: f32 = 0.01;
+
+/// Change in temperature per tick that wakes a live tile, as it may be about to melt or catch fire.
+const WAKE_TEMPERATURE_CHANGE: f32 = 0.01;
+
+/// Fraction of the temperature difference crossing an edge per tick at full conductivity. Kept
+/// below a quarter so a cell can't overshoot even when all four neighbours pull on it.
+const DIFFUSION_RATE: f32 = 0.2;
+
+/// Largest change in temperature per tick below which a chunk counts as settled and stops
+/// diffusing, until something changes in or next to it.
+const SETTLED_TEMPERATURE_CHANGE: f32 = 0.001;
+
+/// Width and height of a chunk along with the ring of cells around it.
+const RINGED_CHUNK_SIZE: usize = CHUNK_SIZE as usize + 2;
+const RINGED_CHUNK_AREA: usize = RINGED_CHUNK_SIZE * RINGED_CHUNK_SIZE;
+
+/// Conductivity and heat capacity of a tile.
+pub(crate) fn thermal_properties(tile: Tile, materials: &MaterialRegistry) -> (f32, f32) {
+    match tile.get_material() {
+        Some(material) => {
+            let material = materials.get(material);
+            (material.conductivity, material.heat_capacity)
+        }
+        None => (AIR_CONDUCTIVITY, AIR_HEAT_CAPACITY)
+    }
+}
+
+/// How a temperature is tinted in the overlay: blue when cold, red to yellow when hot, and a
+/// strength from 0 (no tint) to 1.
+fn temperature_tint(temperature: f32) -> (Color, f32) {
+    let difference = temperature - AMBIENT_TEMPERATURE;
+
+    if difference < 0.0 {
+        (Color::new(40, 90, 255), (-difference / 40.0).min(1.0))
+    } else {
+        // Red at 100 °C above ambient, fading to yellow towards 1000 °C
+        let heat = ((difference - 100.0) / 900.0).clamp(0.0, 1.0);
+        (Color::new(255, (40.0 + heat * 210.0) as u8, 0), (difference / 100.0).min(1.0))
+    }
+}
+
+fn blend(color: Color, tint: Color, strength: f32) -> Color {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * strength) as u8;
+
+    Color::new(mix(color.r, tint.r), mix(color.g, tint.g), mix(color.b, tint.b))
+}
+
+impl World {
+    pub fn temperatures(&self) -> &[f32] {
+        &self.temperatures
+    }
+
+    pub fn get_temperature(&self, tile_position: Vector2<u32>) -> f32 {
+        self.temperatures[(tile_position.x + tile_position.y * self.world_width) as usize]
+    }
+
+    pub fn set_temperature(&mut self, tile_position: Vector2<u32>, temperature: f32) {
+        self.temperatures[(tile_position.x + tile_position.y * self.world_width) as usize] = temperature;
+        self.chunks.mark_heating(tile_position);
+    }
+
+    /// Adds `heat` to the cell at `tile_position`, raising its temperature by `heat` divided by the
+    /// heat capacity of its tile. Negative amounts cool it down. Wakes the cell, so its tile gets
+    /// to react to the change.
+    pub fn add_heat(&mut self, tile_position: Vector2<u32>, heat: f32) {
+        let (_, heat_capacity) = thermal_properties(self.get_tile(tile_position), &self.materials);
+        let temperature = self.get_temperature(tile_position);
+
+        self.set_temperature(tile_position, temperature + heat / heat_capacity);
+        self.wake(tile_position);
+    }
+
+    /// Runs one tick of heat diffusion within the chunks that need it.
+    ///
+    /// Each of those works out the changes of its own cells and of the ring of cells around it,
+    /// so heat crossing into a settled chunk ends up there too and wakes it if it's enough.
+    pub(crate) fn diffuse_heat(&mut self) {
+        let chunk_count = self.chunks.size();
+        let diffusing = self.chunks.begin_heat_tick();
+
+        let amount_of_changes = diffusing.len() * RINGED_CHUNK_AREA;
+        if self.heat_changes.len() != amount_of_changes {
+            self.heat_changes = vec![0.0; amount_of_changes];
+        }
+
+        let tiles = &self.tiles;
+        let temperatures = &self.temperatures;
+        let materials = &self.materials;
+        let world_width = self.world_width;
+        let world_height = self.world_height;
+
+        let chunk_at = |chunk_index: usize| {
+            RingedChunk::new(Vector2::new(chunk_index as u32 % chunk_count.x, chunk_index as u32 / chunk_count.x), world_width, world_height)
+        };
+        let work_out_changes = |(chunk_index, changes): (usize, &mut [f32])| {
+            if diffusing[chunk_index] {
+                chunk_at(chunk_index).work_out_changes(changes, tiles, temperatures, materials);
+            }
+        };
+
+        // Chunks only read temperatures here, so they are worked out in parallel
+        if self.multithreaded {
+            self.heat_changes.par_chunks_mut(RINGED_CHUNK_AREA).enumerate().for_each(work_out_changes);
+        } else {
+            self.heat_changes.chunks_mut(RINGED_CHUNK_AREA).enumerate().for_each(work_out_changes);
+        }
+
+        let temperatures = &mut self.temperatures;
+        let chunks = &mut self.chunks;
+
+        // Applies a change to a cell, which keeps its chunk diffusing if it's large enough
+        let mut apply = |tile_position: Vector2<u32>, change: f32, cools: bool| {
+            let index = (tile_position.x + tile_position.y * world_width) as usize;
+            let temperature = &mut temperatures[index];
+            let previous_temperature = *temperature;
+
+            *temperature += change;
+
+            match tiles[index] {
+                Tile::Empty if cools => *temperature += (AMBIENT_TEMPERATURE - *temperature) * AIR_COOLING_RATE,
+                Tile::LiveTile(_) if change.abs() > WAKE_TEMPERATURE_CHANGE => chunks.mark(tile_position),
+                _ => {}
+            }
+
+            if (*temperature - previous_temperature).abs() > SETTLED_TEMPERATURE_CHANGE {
+                chunks.mark_heating(tile_position);
+            }
+        };
+
+        for (chunk_index, changes) in self.heat_changes.chunks(RINGED_CHUNK_AREA).enumerate() {
+            if !diffusing[chunk_index] {
+                continue;
+            }
+
+            let chunk = chunk_at(chunk_index);
+
+            for y in 1..=chunk.size.y {
+                for x in 1..=chunk.size.x {
+                    let local_position = Vector2::new(x, y);
+                    let tile_position = chunk.world_position(local_position);
+
+                    apply(tile_position, changes[RingedChunk::local_index(local_position)], true);
+                }
+            }
+
+            // Ring cells of diffusing chunks get the same heat from their own chunk. The others
+            // only get the heat that crossed over and cool down once their chunk diffuses.
+            chunk.for_each_ring_cell(|local_position| {
+                let tile_position = chunk.world_position(local_position);
+                if diffusing[(tile_position.x / CHUNK_SIZE + tile_position.y / CHUNK_SIZE * chunk_count.x) as usize] {
+                    return;
+                }
+
+                apply(tile_position, changes[RingedChunk::local_index(local_position)], false);
+            });
+        }
+    }
+
+    /// Renders like `World::render`, with every cell tinted by its temperature.
+    pub fn render_temperature(&self, frame: &mut [u8]) {
+        self.render(frame);
+
+        for (index, &temperature) in self.temperatures.iter().enumerate() {
+            let (tint, strength) = temperature_tint(temperature);
+            if strength <= 0.0 {
+                continue;
+            }
+
+            let pixel = &mut frame[index * 4..index * 4 + 3];
+            let color = blend(Color::new(pixel[0], pixel[1], pixel[2]), tint, strength * 0.75);
+
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+}
+
+/// A chunk along with the ring of cells around it that lie within the world. Local positions
+/// start at the ring's top left corner, so the chunk's own cells are at 1 to `size`.
+struct RingedChunk {
+    /// Position of the chunk's top left cell.
+    min: Vector2<u32>,
+    /// Size of the chunk, which is smaller than `CHUNK_SIZE` along the far edges of the world.
+    size: Vector2<u32>,
+    /// Whether the ring exists on the left, right, top and bottom side.
+    ring: [bool; 4],
+    world_width: u32
+}
+
+impl RingedChunk {
+    fn new(chunk_position: Vector2<u32>, world_width: u32, world_height: u32) -> RingedChunk {
+        let min = chunk_position * CHUNK_SIZE;
+        let size = Vector2::new((world_width - min.x).min(CHUNK_SIZE), (world_height - min.y).min(CHUNK_SIZE));
+
+        RingedChunk {
+            min,
+            size,
+            ring: [min.x > 0, min.x + size.x < world_width, min.y > 0, min.y + size.y < world_height],
+            world_width
+        }
+    }
+
+    fn local_index(local_position: Vector2<u32>) -> usize {
+        local_position.x as usize + local_position.y as usize * RINGED_CHUNK_SIZE
+    }
+
+    fn world_position(&self, local_position: Vector2<u32>) -> Vector2<u32> {
+        self.min + local_position - Vector2::new(1, 1)
+    }
+
+    /// Calls `f` with the local position of every ring cell next to one of the chunk's cells.
+    fn for_each_ring_cell<F: FnMut(Vector2<u32>)>(&self, mut f: F) {
+        let [left, right, top, bottom] = self.ring;
+
+        for y in 1..=self.size.y {
+            if left {
+                f(Vector2::new(0, y));
+            }
+            if right {
+                f(Vector2::new(self.size.x + 1, y));
+            }
+        }
+        for x in 1..=self.size.x {
+            if top {
+                f(Vector2::new(x, 0));
+            }
+            if bottom {
+                f(Vector2::new(x, self.size.y + 1));
+            }
+        }
+    }
+
+    /// Works out the change in temperature of every cell of the chunk and its ring into
+    /// `changes`, indexed by `RingedChunk::local_index`.
+    fn work_out_changes(&self, changes: &mut [f32], tiles: &[Tile], temperatures: &[f32], materials: &MaterialRegistry) {
+        let [left, right, top, bottom] = self.ring;
+        // The local positions of the cells within the world
+        let first = Vector2::new(!left as u32, !top as u32);
+        let last = Vector2::new(self.size.x + right as u32, self.size.y + bottom as u32);
+
+        let world_index = |local_position: Vector2<u32>| {
+            let tile_position = self.world_position(local_position);
+            (tile_position.x + tile_position.y * self.world_width) as usize
+        };
+
+        let mut properties = [(0.0, 0.0); RINGED_CHUNK_AREA];
+        let mut local_temperatures = [0.0; RINGED_CHUNK_AREA];
+        for y in first.y..=last.y {
+            for x in first.x..=last.x {
+                let local_position = Vector2::new(x, y);
+                let index = world_index(local_position);
+                let local_index = RingedChunk::local_index(local_position);
+
+                properties[local_index] = thermal_properties(tiles[index], materials);
+                local_temperatures[local_index] = temperatures[index];
+            }
+        }
+
+        // Heat crossing the edge between two cells, sized so neither side moves more than `DIFFUSION_RATE` of the difference
+        let edge_heat = |index: usize, other_index: usize| {
+            let (conductivity, heat_capacity) = properties[index];
+            let (other_conductivity, other_heat_capacity) = properties[other_index];
+
+            conductivity.min(other_conductivity) * DIFFUSION_RATE * (local_temperatures[index] - local_temperatures[other_index])
+                * heat_capacity * other_heat_capacity / (heat_capacity + other_heat_capacity)
+        };
+
+        // Heat flowing from every cell to its right and lower neighbour, 0 where there is none
+        let mut right_heat = [0.0; RINGED_CHUNK_AREA];
+        let mut down_heat = [0.0; RINGED_CHUNK_AREA];
+        for y in 1..=self.size.y {
+            for x in first.x..last.x {
+                let index = RingedChunk::local_index(Vector2::new(x, y));
+                right_heat[index] = edge_heat(index, index + 1);
+            }
+        }
+        for y in first.y..last.y {
+            for x in 1..=self.size.x {
+                let index = RingedChunk::local_index(Vector2::new(x, y));
+                down_heat[index] = edge_heat(index, index + RINGED_CHUNK_SIZE);
+            }
+        }
+
+        for y in 1..=self.size.y {
+            for x in 1..=self.size.x {
+                let index = RingedChunk::local_index(Vector2::new(x, y));
+                let heat = right_heat[index - 1] + down_heat[index - RINGED_CHUNK_SIZE] - right_heat[index] - down_heat[index];
+
+                changes[index] = heat / properties[index].1;
+            }
+        }
+
+        // Ring cells only exchange heat with the chunk's cells next to them
+        self.for_each_ring_cell(|local_position| {
+            let index = RingedChunk::local_index(local_position);
+            let heat = if local_position.x == 0 {
+                -right_heat[index]
+            } else if local_position.x > self.size.x {
+                right_heat[index - 1]
+            } else if local_position.y == 0 {
+                -down_heat[index]
+            } else {
+                down_heat[index - RINGED_CHUNK_SIZE]
+            };
+
+            changes[index] = heat / properties[index].1;
+        });
+    }
+}
@@ -1,52 +1,89 @@
 ﻿use crate::{Color, BACKGROUND_COLOR};
-use crate::live_tiles::{SandTile, WaterTile};
+use crate::live_tiles;
+use crate::material::{MaterialId, MaterialRegistry, Material, Behavior};
+use crate::world::WorldRng;
+use crate::temperature::AMBIENT_TEMPERATURE;
+use crate::particle::Particle;
+use crate::command::{TileCommand, WorldEvent, EventKind};
+use crate::schedule::Region;
+use crate::boundary::{Boundaries, Resolved};
 use cgmath::{Vector2, ElementWise};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct LiveTile {
-    pub state: LiveTileState,
+    pub material: MaterialId,
+    pub color: Color,
+    pub frames_since_color_change: u16,
+    /// Heat taken in (positive) or given off (negative) towards a phase transition.
+    pub absorbed_heat: f32,
+    /// Ticks until the tile decays, for materials with a lifetime and for fires.
+    pub ticks_left: u16,
+    /// How many more tiles a corrosive tile can dissolve.
+    pub strength: u16,
     pub last_frame_updated: u64
 }
 
 impl LiveTile {
-    pub fn new(state: LiveTileState) -> LiveTile {
+    pub fn new(material: MaterialId, color: Color) -> LiveTile {
         LiveTile {
-            state,
+            material,
+            color,
+            frames_since_color_change: 0,
+            absorbed_heat: 0.0,
+            ticks_left: 0,
+            strength: 0,
             last_frame_updated: 0
         }
     }
-}
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum LiveTileState {
-    Sand(SandTile),
-    Water(WaterTile)
-}
+    pub fn update(&mut self, mut api: LiveTileApi) -> LiveTileInstruction {
+        let material = api.materials.get(self.material);
 
-impl LiveTileState {
-    pub fn get_color(self) -> Color {
-        match self {
-            LiveTileState::Sand(sand_tile) => sand_tile.color,
-            LiveTileState::Water(water_tile) => water_tile.color
+        if let Some(source_temperature) = material.source_temperature {
+            api.set_temperature(Vector2::new(0, 0), source_temperature);
         }
-    }
 
-    pub fn update(&mut self, api: LiveTileApi) -> LiveTileInstruction {
-        match self {
-            LiveTileState::Sand(sand_tile) => sand_tile.update(api),
-            LiveTileState::Water(water_tile) => water_tile.update(api)
+        if let Some(instruction) = live_tiles::update_phase(self, &mut api) {
+            return instruction;
+        }
+        if let Some(instruction) = live_tiles::update_combustion(self, &mut api) {
+            return instruction;
+        }
+        if let Some(instruction) = live_tiles::update_lifetime(self, &mut api) {
+            return instruction;
+        }
+        if let Some(instruction) = live_tiles::update_corrosion(self, &mut api) {
+            return instruction;
+        }
+
+        if let Some(recolor_interval) = material.recolor_interval {
+            self.frames_since_color_change += 1;
+            if self.frames_since_color_change >= recolor_interval {
+                self.color = material.random_color(api.rng);
+                self.frames_since_color_change = 0;
+            }
+        }
+
+        match material.behavior {
+            Behavior::Powder => live_tiles::update_powder(self, api),
+            Behavior::Liquid => live_tiles::update_liquid(self, api),
+            Behavior::Gas => live_tiles::update_gas(self, api),
+            Behavior::Fire => live_tiles::update_fire(self, api),
+            Behavior::Static => LiveTileInstruction::None
         }
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct StaticTile {
-    color: Color
+    pub material: MaterialId,
+    pub color: Color
 }
 
 impl StaticTile {
-    pub fn new(color: Color) -> StaticTile {
+    pub fn new(material: MaterialId, color: Color) -> StaticTile {
         StaticTile {
+            material,
             color
         }
     }
@@ -64,36 +101,140 @@ impl Tile {
         match self {
             Tile::Empty => BACKGROUND_COLOR,
             Tile::StaticTile(static_tile) => static_tile.color,
-            Tile::LiveTile(live_tile) => live_tile.state.get_color()
+            Tile::LiveTile(live_tile) => live_tile.color
+        }
+    }
+
+    pub fn get_material(self) -> Option<MaterialId> {
+        match self {
+            Tile::Empty => None,
+            Tile::StaticTile(static_tile) => Some(static_tile.material),
+            Tile::LiveTile(live_tile) => Some(live_tile.material)
         }
     }
 }
 
-#[derive(Copy, Clone)]
 pub struct LiveTileApi<'a> {
     pub tile_position: Vector2<u32>,
-    pub tiles: &'a Vec<Tile>,
+    /// Every tile of the world, as it was before the current chunk update. The cells in `region`
+    /// are up to date.
+    pub tiles: &'a [Tile],
+    /// Temperature of every cell, like `tiles`.
+    pub temperatures: &'a [f32],
+    /// The cells around the tile it can change.
+    pub region: &'a mut Region,
     pub world_width: u32,
-    pub world_height: u32
+    pub world_height: u32,
+    pub boundaries: Boundaries,
+    pub materials: &'a MaterialRegistry,
+    /// Changes outside the tile's own cell, applied once every tile has been updated.
+    pub commands: &'a mut Vec<TileCommand>,
+    pub rng: &'a mut WorldRng
 }
 
 impl<'a> LiveTileApi<'a> {
-    pub fn get(self, tile_offset: Vector2<i32>) -> Tile {
+    /// Where `tile_offset` leads to, which may be across an edge of the world.
+    pub fn resolve(&self, tile_offset: Vector2<i32>) -> Resolved {
+        let tile_position = tile_offset.add_element_wise(self.tile_position.cast().unwrap());
+        self.boundaries.resolve(tile_position, self.world_width, self.world_height)
+    }
 
-        let new_tile_position = tile_offset.add_element_wise(self.tile_position.cast().unwrap());
+    /// The cell `tile_offset` leads to, `None` past a wall or into a void.
+    pub(crate) fn get_tile_position(&self, tile_offset: Vector2<i32>) -> Option<Vector2<u32>> {
+        match self.resolve(tile_offset) {
+            Resolved::Inside(tile_position) => Some(tile_position),
+            Resolved::Wall | Resolved::Void => None
+        }
+    }
 
-        if new_tile_position.x < 0 || new_tile_position.x >= self.world_width as i32 || new_tile_position.y < 0 || new_tile_position.y >= self.world_height as i32 {
-            return Tile::StaticTile(StaticTile::new((255, 0, 0).into()));
+    /// The tile at `tile_offset`. Past a wall it's a boundary tile and in a void it's empty. Tiles
+    /// further away than `schedule::MAX_REACH` may have changed since and shouldn't be looked at.
+    pub fn get(&self, tile_offset: Vector2<i32>) -> Tile {
+        let tile_position = match self.resolve(tile_offset) {
+            Resolved::Inside(tile_position) => tile_position,
+            Resolved::Wall => return Tile::StaticTile(StaticTile::new(MaterialId::BOUNDARY, (255, 0, 0).into())),
+            Resolved::Void => return Tile::Empty
+        };
+
+        match self.region.get_index(tile_position) {
+            Some(region_index) => self.region.tiles[region_index],
+            None => self.tiles[(tile_position.x + tile_position.y * self.world_width) as usize]
         }
+    }
 
-        let new_tile_position: Vector2<u32> = new_tile_position.cast().unwrap();
-        let tile_index = (new_tile_position.x + new_tile_position.y * self.world_width) as usize;
+    pub fn is_empty(&self, tile_offset: Vector2<i32>) -> bool {
+        self.get(tile_offset) == Tile::Empty
+    }
+
+    /// The material of the tile at `tile_offset`, or `None` if it's empty.
+    pub fn get_material(&self, tile_offset: Vector2<i32>) -> Option<&'a Material> {
+        self.get(tile_offset)
+            .get_material()
+            .map(|material| self.materials.get(material))
+    }
+
+    /// The temperature at `tile_offset`, outside the world it's always the ambient temperature.
+    pub fn get_temperature(&self, tile_offset: Vector2<i32>) -> f32 {
+        let tile_position = match self.get_tile_position(tile_offset) {
+            Some(tile_position) => tile_position,
+            None => return AMBIENT_TEMPERATURE
+        };
+
+        match self.region.get_index(tile_position) {
+            Some(region_index) => self.region.temperatures[region_index],
+            None => self.temperatures[(tile_position.x + tile_position.y * self.world_width) as usize]
+        }
+    }
+
+    /// Sets the temperature at `tile_offset`, does nothing outside the tile's region. Use
+    /// `LiveTileApi::add_heat` for cells further away.
+    pub fn set_temperature(&mut self, tile_offset: Vector2<i32>, temperature: f32) {
+        if let Some(region_index) = self.get_tile_position(tile_offset).and_then(|tile_position| self.region.get_index(tile_position)) {
+            self.region.temperatures[region_index] = temperature;
+        }
+    }
+
+    /// Queues adding `heat` to the tile at `tile_offset`, see `World::add_heat`. Does nothing
+    /// outside the world.
+    pub fn add_heat(&mut self, tile_offset: Vector2<i32>, heat: f32) {
+        if let Some(position) = self.get_tile_position(tile_offset) {
+            self.commands.push(TileCommand::AddHeat { position, heat });
+        }
+    }
+
+    /// Queues replacing the tile at `tile_offset`. It only happens if no other tile claims the
+    /// cell first and it still holds the same material by the end of the tick. Does nothing
+    /// outside the world.
+    pub fn set_tile(&mut self, tile_offset: Vector2<i32>, tile: Tile) {
+        if let Some(position) = self.get_tile_position(tile_offset) {
+            let expected = self.get(tile_offset).get_material();
+            self.commands.push(TileCommand::SetTile { position, expected, tile });
+        }
+    }
+
+    /// Queues turning the tile at `tile_offset` into a particle flying off with `velocity`, on the
+    /// same conditions as `LiveTileApi::set_tile`. Does nothing for empty cells or outside the world.
+    pub fn launch_tile(&mut self, tile_offset: Vector2<i32>, velocity: Vector2<f32>) {
+        if let (Some(position), Some(expected)) = (self.get_tile_position(tile_offset), self.get(tile_offset).get_material()) {
+            self.commands.push(TileCommand::LaunchTile { position, expected, velocity });
+        }
+    }
+
+    /// Queues a particle launched from this tile.
+    pub fn spawn_particle(&mut self, tile: Tile, velocity: Vector2<f32>) {
+        let position = self.tile_position.cast().unwrap();
+        self.commands.push(TileCommand::SpawnParticle(Particle::new(tile, position, velocity)));
+    }
 
-        self.tiles[tile_index]
+    /// Keeps this tile updating even though nothing around it changed, for tiles that could
+    /// still do something but happened not to this tick.
+    pub fn keep_awake(&mut self) {
+        self.commands.push(TileCommand::Wake(self.tile_position));
     }
 
-    pub fn is_empty(self, tile_offset: Vector2<i32>) -> bool {
-        return self.get(tile_offset) == Tile::Empty
+    /// Queues an event at this tile.
+    pub fn emit_event(&mut self, kind: EventKind) {
+        self.commands.push(TileCommand::Event(WorldEvent { kind, position: self.tile_position }));
     }
 }
 
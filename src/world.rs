@@ -1,24 +1,61 @@
 ﻿use crate::{Color};
-use crate::tile::{Tile, LiveTileApi, LiveTileInstruction};
-use cgmath::{Vector2, ElementWise};
+use crate::tile::{Tile, LiveTileApi};
+use cgmath::Vector2;
 use crate::particle::{Particle, ParticleInstructions};
-use retain_mut::RetainMut;
+use crate::material::{MaterialId, MaterialRegistry};
+use crate::temperature::AMBIENT_TEMPERATURE;
+use crate::command::{TileCommand, WorldEvent};
+use crate::chunk::Chunks;
+use crate::schedule::{Region, ScanOrder};
+use crate::boundary::{Boundaries, ParticleBoundary};
+use rand::{SeedableRng, random};
+use rand_pcg::Pcg32;
+use std::sync::Arc;
+
+/// The random number generator owned by a `World`.
+///
+/// Every random decision in the simulation is drawn from it, so a world created with
+/// the same seed and fed the same edits evolves identically.
+pub type WorldRng = Pcg32;
 
 pub struct World {
-    tiles: Vec<Tile>,
-    particles: Vec<Particle>,
+    pub(crate) tiles: Vec<Tile>,
+    /// Temperature of every cell in °C, indexed like `tiles`.
+    pub(crate) temperatures: Vec<f32>,
+    pub(crate) particles: Vec<Particle>,
+    pub(crate) rng: WorldRng,
+    pub(crate) seed: u64,
+    pub(crate) materials: Arc<MaterialRegistry>,
+    /// Commands queued by live tiles during the current tick.
+    pub(crate) commands: Vec<TileCommand>,
+    pub(crate) events: Vec<WorldEvent>,
+    pub(crate) chunks: Chunks,
+    pub(crate) multithreaded: bool,
+    pub(crate) scan_order: ScanOrder,
+    pub(crate) boundaries: Boundaries,
+    /// Temperature changes worked out by `World::diffuse_heat`, kept to spare allocating them
+    /// every tick.
+    pub(crate) heat_changes: Vec<f32>,
     pub frame: Vec<u8>,
     pub world_width: u32,
     pub world_height: u32
 }
 
 impl World {
+    /// Creates a world seeded from entropy. Use `World::with_seed` for reproducible runs.
     pub fn new(initial_color: Color, world_width: u32, world_height: u32) -> World {
-        let total_amount_of_tiles: usize = (world_width * world_height) as usize;
+        World::with_seed(initial_color, world_width, world_height, random())
+    }
 
-        let mut frame: Vec<u8> = Vec::with_capacity(total_amount_of_tiles * 4);
-        
-        frame.resize(total_amount_of_tiles * 4, 0);
+    /// Creates a world with the built-in materials.
+    pub fn with_seed(initial_color: Color, world_width: u32, world_height: u32, seed: u64) -> World {
+        World::with_materials(initial_color, world_width, world_height, seed, MaterialRegistry::builtin())
+    }
+
+    pub fn with_materials(initial_color: Color, world_width: u32, world_height: u32, seed: u64, materials: Arc<MaterialRegistry>) -> World {
+        let total_amount_of_tiles = world_width as usize * world_height as usize;
+
+        let mut frame: Vec<u8> = vec![0; total_amount_of_tiles * 4];
 
         for chunk in frame.chunks_exact_mut(4) {
             chunk[0] = initial_color.r;
@@ -29,28 +66,83 @@ impl World {
 
         World {
             tiles: vec![Tile::Empty; total_amount_of_tiles],
+            temperatures: vec![AMBIENT_TEMPERATURE; total_amount_of_tiles],
             particles: Vec::new(),
+            rng: WorldRng::seed_from_u64(seed),
+            seed,
+            materials,
+            commands: Vec::new(),
+            events: Vec::new(),
+            chunks: Chunks::new(world_width, world_height),
+            multithreaded: true,
+            scan_order: ScanOrder::default(),
+            boundaries: Boundaries::default(),
+            heat_changes: Vec::new(),
             frame,
             world_width,
             world_height
         }
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The world's random number generator, for edits that should stay deterministic.
+    pub fn rng(&mut self) -> &mut WorldRng {
+        &mut self.rng
+    }
+
+    pub fn materials(&self) -> &Arc<MaterialRegistry> {
+        &self.materials
+    }
+
+    /// Creates a new tile of `material`, drawing its color from the world's RNG.
+    pub fn create_tile(&mut self, material: MaterialId) -> Tile {
+        self.materials.create_tile(material, &mut self.rng)
+    }
+
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
     fn get_tile_index(&self, tile_position: Vector2<u32>) -> usize {
         (tile_position.x + tile_position.y * self.world_width) as usize
     }
 
     pub fn set_tile(&mut self, tile_position: Vector2<u32>, tile: Tile) {
+        self.write_tile(tile_position, tile);
+        self.chunks.mark(tile_position);
+    }
+
+    /// Sets a tile without waking the cells around it.
+    pub(crate) fn write_tile(&mut self, tile_position: Vector2<u32>, tile: Tile) {
         let tile_index = self.get_tile_index(tile_position);
         self.tiles[tile_index] = tile;
         let color = tile.get_color();
 
-        self.frame[(tile_index * 4) + 0] = color.r;
+        self.frame[tile_index * 4] = color.r;
         self.frame[(tile_index * 4) + 1] = color.g;
         self.frame[(tile_index * 4) + 2] = color.b;
         self.frame[(tile_index * 4) + 3] = 255;
     }
 
+    /// Sets a tile the way painting one does: the cell takes on the material's initial
+    /// temperature, if it has one.
+    pub fn place_tile(&mut self, tile_position: Vector2<u32>, tile: Tile) {
+        self.set_tile(tile_position, tile);
+
+        if let Some(material) = tile.get_material() {
+            if let Some(initial_temperature) = self.materials.get(material).initial_temperature {
+                self.set_temperature(tile_position, initial_temperature);
+            }
+        }
+    }
+
     pub fn get_tile(&self, tile_position: Vector2<u32>) -> Tile {
         let tile_index = self.get_tile_index(tile_position);
         self.tiles[tile_index]
@@ -61,70 +153,49 @@ impl World {
     }
 
     pub fn update(&mut self, delta_time: f32, current_frame: u64) {
-        for x in 0..self.world_width {
-            for y in 0..self.world_height {
-                let tile_position = Vector2::new(x, y);
-                let tile_index = self.get_tile_index(tile_position);
-    
-                let tile = &mut self.tiles[tile_index];
-    
-                match tile {
-                    Tile::Empty => {},
-                    Tile::StaticTile(_) => {},
-                    Tile::LiveTile(mut live_tile) => {
-                        if live_tile.last_frame_updated == current_frame {
-                            continue;
-                        }
-
-                        let live_tile_instruction = live_tile.state.update(LiveTileApi {
-                            tile_position,
-                            tiles: &self.tiles,
-                            world_width: self.world_width,
-                            world_height: self.world_height
-                        });
-                        
-                        match live_tile_instruction {
-                            LiveTileInstruction::None => {
-                                self.set_tile(tile_position, Tile::LiveTile(live_tile))
-                            },
-                            LiveTileInstruction::Replace(tile_offset) => {
-                                let new_tile_position: Vector2<u32> = tile_offset.add_element_wise(tile_position.cast().unwrap()).cast().unwrap();
-
-                                live_tile.last_frame_updated = current_frame;
-
-                                self.set_tile(tile_position, Tile::Empty);
-                                self.set_tile(new_tile_position, Tile::LiveTile(live_tile))
-                            }
-                            LiveTileInstruction::Switch(tile_offset) =>  {
-                                let new_tile_position: Vector2<u32> = tile_offset.add_element_wise(tile_position.cast().unwrap()).cast().unwrap();
-
-                                live_tile.last_frame_updated = current_frame;
-                                
-                                let other_tile_index = self.get_tile_index(new_tile_position);
-                                let other_tile = self.tiles[other_tile_index];
-
-                                self.set_tile(tile_position, other_tile);
-                                self.set_tile(new_tile_position, Tile::LiveTile(live_tile))
-                            }
-                            LiveTileInstruction::ReplaceSelfWith(replacement_tile) => {
-                                self.set_tile(tile_position, replacement_tile);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        self.events.clear();
+
+        self.update_chunks(current_frame);
+
+        self.diffuse_heat();
         
         let tiles = &self.tiles;
         let world_width = self.world_width;
         let world_height = self.world_height;
+        let boundaries = self.boundaries;
+        let materials = &self.materials;
+        let temperatures = &self.temperatures;
+        let commands = &mut self.commands;
+        let rng = &mut self.rng;
         
         let mut new_tiles: Vec<(Vector2<u32>, Tile)> = Vec::new();
 
         for i in (0..self.particles.len()).rev() {
             let particle = &mut self.particles[i];
             
-            let particle_instructions = particle.update(delta_time, tiles, world_width, world_height);
+            particle.apply_velocity(delta_time);
+            let landed = match boundaries.constrain_particle(particle, world_width, world_height) {
+                ParticleBoundary::Inside => false,
+                ParticleBoundary::Landed => true,
+                ParticleBoundary::Gone => {
+                    self.particles.remove(i);
+                    continue;
+                }
+            };
+
+            let api = LiveTileApi {
+                tile_position: particle.position.cast().unwrap(),
+                tiles,
+                temperatures,
+                region: &mut Region::empty(),
+                world_width,
+                world_height,
+                boundaries,
+                materials,
+                commands,
+                rng
+            };
+            let particle_instructions = if landed { particle.land(api) } else { particle.update(api) };
             
             match particle_instructions {
                 ParticleInstructions::None => {},
@@ -139,33 +210,35 @@ impl World {
         }
 
         for (tile_position, tile) in new_tiles {
-            self.set_tile(tile_position, tile);
+            self.place_tile(tile_position, tile);
         }
+
+        // Commands see the tiles as they are at the end of the tick, landed particles included
+        self.apply_commands();
     }
     
-    pub fn render(&mut self, frame: &mut[u8]) {
+    pub fn render(&self, frame: &mut [u8]) {
         frame.copy_from_slice(&self.frame);
 
         let world_width = self.world_width;
         let world_height = self.world_height;
         
-        self.particles.retain_mut(|particle| {
+        for particle in &self.particles {
             let position = particle.position;
             let color = particle.tile.get_color();
             
+            // Particles only leave the world through its boundaries, but may have been added anywhere
             if position.x < 0.0 || position.y < 0.0 || position.x >= world_width as f32 || position.y >= world_height as f32 {
-                return false;
+                continue;
             }
             
             let position: Vector2<u32> = position.cast().unwrap();
             let frame_index = (position.x + position.y * world_width) as usize;
 
-            frame[frame_index * 4 + 0] = color.r;
+            frame[frame_index * 4] = color.r;
             frame[frame_index * 4 + 1] = color.g;
             frame[frame_index * 4 + 2] = color.b;
             frame[frame_index * 4 + 3] = 255;
-            
-            true
-        });
+        }
     }
 }
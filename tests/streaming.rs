@@ -0,0 +1,78 @@
+//! Chunks paged out of a streaming world have to come back the way they left.
+
+use cgmath::Vector2;
+use powpowder::streaming::StreamingWorld;
+use powpowder::{MaterialRegistry, Tile};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const AREA_SIZE: u32 = 3;
+/// Far enough for every chunk of the area to be written to disk.
+const FAR_AWAY: i64 = 1024;
+
+fn directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("powpowder-streaming-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory
+}
+
+/// A streaming world with a few stones scattered over its chunks.
+fn stony_world(directory: &Path) -> StreamingWorld {
+    let mut streaming_world = StreamingWorld::new(directory, Vector2::new(AREA_SIZE, AREA_SIZE), 0, MaterialRegistry::builtin()).unwrap();
+    let world = streaming_world.world_mut();
+    let stone = world.materials().find("stone").unwrap();
+
+    for i in 0..world.world_width {
+        let tile = world.create_tile(stone);
+        world.place_tile(Vector2::new(i, (i * 7) % world.world_height), tile);
+    }
+
+    streaming_world
+}
+
+fn area_tiles(streaming_world: &StreamingWorld) -> Vec<Tile> {
+    streaming_world.world().tiles().to_vec()
+}
+
+#[test]
+fn chunks_come_back_from_disk() {
+    let directory = directory("round-trip");
+    let mut streaming_world = stony_world(&directory);
+    let origin = streaming_world.origin();
+    let tiles = area_tiles(&streaming_world);
+
+    streaming_world.set_camera(Vector2::new(FAR_AWAY, 0)).unwrap();
+    assert_ne!(streaming_world.origin(), origin);
+    assert_eq!(streaming_world.get_tile(origin), None, "the old area should be on disk");
+
+    streaming_world.set_camera(Vector2::new(0, 0)).unwrap();
+    assert_eq!(streaming_world.origin(), origin);
+    assert!(area_tiles(&streaming_world) == tiles, "tiles changed on their way through the disk");
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn failing_writes_keep_the_area() {
+    let directory = directory("failing-writes");
+    let mut streaming_world = stony_world(&directory);
+    let origin = streaming_world.origin();
+    let tiles = area_tiles(&streaming_world);
+
+    // Chunks can't be created in a directory that has become a file
+    fs::remove_dir_all(&directory).unwrap();
+    fs::write(&directory, b"").unwrap();
+
+    assert!(streaming_world.set_camera(Vector2::new(FAR_AWAY, 0)).is_err());
+    assert_eq!(streaming_world.origin(), origin);
+    assert!(area_tiles(&streaming_world) == tiles, "a failed move changed the area");
+
+    // Once the disk works again, nothing was lost
+    fs::remove_file(&directory).unwrap();
+    fs::create_dir_all(&directory).unwrap();
+    streaming_world.set_camera(Vector2::new(FAR_AWAY, 0)).unwrap();
+    streaming_world.set_camera(Vector2::new(0, 0)).unwrap();
+    assert!(area_tiles(&streaming_world) == tiles, "tiles were lost after a failed move");
+
+    fs::remove_dir_all(&directory).unwrap();
+}
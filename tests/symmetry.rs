@@ -0,0 +1,69 @@
+//! A symmetric pile has to settle symmetrically, whichever way the scan order goes first.
+
+use cgmath::Vector2;
+use powpowder::schedule::ScanOrder;
+use powpowder::{World, BACKGROUND_COLOR};
+
+const WORLD_WIDTH: u32 = 97;
+const WORLD_HEIGHT: u32 = 64;
+/// The middle of a chunk, so the chunk grid is symmetric around the pile too.
+const CENTER: u32 = 48;
+const PILE_HALF_WIDTH: u32 = 6;
+const PILE_HEIGHT: u32 = 30;
+const TICKS: u64 = 200;
+const SEEDS: u64 = 6;
+
+/// Drops a column of `material` in the middle of the world, lets it settle and returns how far,
+/// in tiles, its center of mass ended up from the middle.
+fn settled_offset(material: &str, scan_order: ScanOrder, seed: u64) -> f64 {
+    let mut world = World::with_seed(BACKGROUND_COLOR, WORLD_WIDTH, WORLD_HEIGHT, seed);
+    world.set_scan_order(scan_order);
+    let material = world.materials().find(material).unwrap();
+
+    for y in 0..PILE_HEIGHT {
+        for x in CENTER - PILE_HALF_WIDTH..=CENTER + PILE_HALF_WIDTH {
+            let tile = world.create_tile(material);
+            world.place_tile(Vector2::new(x, y), tile);
+        }
+    }
+
+    for tick in 1..=TICKS {
+        world.update(1.0 / 60.0, tick);
+    }
+
+    let (sum, count) = world.tiles().iter()
+        .enumerate()
+        .filter(|(_, tile)| tile.get_material() == Some(material))
+        .fold((0.0, 0.0), |(sum, count), (index, _)| {
+            (sum + (index as u32 % WORLD_WIDTH) as f64 - CENTER as f64, count + 1.0)
+        });
+
+    sum / count
+}
+
+fn mean_offset(material: &str, scan_order: ScanOrder) -> f64 {
+    (0..SEEDS).map(|seed| settled_offset(material, scan_order, seed)).sum::<f64>() / SEEDS as f64
+}
+
+#[test]
+fn sand_settles_symmetrically() {
+    for &scan_order in &[ScanOrder::Alternating, ScanOrder::Random] {
+        let offset = mean_offset("sand", scan_order);
+        assert!(offset.abs() < 0.5, "sand drifted {} tiles with {:?}", offset, scan_order);
+    }
+}
+
+#[test]
+fn water_settles_symmetrically() {
+    for &scan_order in &[ScanOrder::Alternating, ScanOrder::Random] {
+        let offset = mean_offset("water", scan_order);
+        assert!(offset.abs() < 1.0, "water drifted {} tiles with {:?}", offset, scan_order);
+    }
+}
+
+#[test]
+fn fixed_scan_order_drifts() {
+    // Guards the test above against a pile that can't drift in the first place
+    let offset = mean_offset("water", ScanOrder::Columns);
+    assert!(offset.abs() > 2.0, "water only drifted {} tiles with a fixed scan order", offset);
+}
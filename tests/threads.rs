@@ -0,0 +1,72 @@
+//! Updating a world on several threads has to give exactly what a single thread gives.
+
+use cgmath::Vector2;
+use powpowder::{Particle, World, BACKGROUND_COLOR};
+use rayon::ThreadPoolBuilder;
+
+const WORLD_WIDTH: u32 = 128;
+const WORLD_HEIGHT: u32 = 96;
+const TICKS: u64 = 150;
+const THREADS: usize = 4;
+
+/// Stripes of powders, liquids and fuel above a stone floor with a lava pool in it, and a spray
+/// of particles, so moving, burning, melting and heat all cross chunk borders.
+fn mixed_world(seed: u64) -> World {
+    let mut world = World::with_seed(BACKGROUND_COLOR, WORLD_WIDTH, WORLD_HEIGHT, seed);
+    let find = |world: &World, id: &str| world.materials().find(id).unwrap();
+    let stripes = [find(&world, "sand"), find(&world, "water"), find(&world, "wood"), find(&world, "oil"), find(&world, "salt")];
+    let stone = find(&world, "stone");
+    let lava = find(&world, "lava");
+    let fire = find(&world, "fire");
+
+    for y in 0..WORLD_HEIGHT {
+        for x in 0..WORLD_WIDTH {
+            let material = if y >= WORLD_HEIGHT - 8 {
+                if (40..88).contains(&x) && y < WORLD_HEIGHT - 3 { lava } else { stone }
+            } else if y < WORLD_HEIGHT / 2 {
+                stripes[((x / 7 + y / 5) % stripes.len() as u32) as usize]
+            } else if y == WORLD_HEIGHT / 2 && x % 9 == 0 {
+                fire
+            } else {
+                continue;
+            };
+
+            let tile = world.create_tile(material);
+            world.place_tile(Vector2::new(x, y), tile);
+        }
+    }
+
+    for i in 0..40 {
+        let tile = world.create_tile(stripes[i % stripes.len()]);
+        let position = Vector2::new(10.0 + i as f32 * 2.7, 60.0);
+        world.add_particle(Particle::new(tile, position, Vector2::new(i as f32 - 20.0, -30.0)));
+    }
+
+    world
+}
+
+fn run(multithreaded: bool, seed: u64) -> World {
+    let mut world = mixed_world(seed);
+    world.set_multithreaded(multithreaded);
+
+    let pool = ThreadPoolBuilder::new().num_threads(THREADS).build().unwrap();
+    pool.install(|| {
+        for tick in 1..=TICKS {
+            world.update(1.0 / 60.0, tick);
+        }
+    });
+
+    world
+}
+
+#[test]
+fn multithreaded_matches_single_threaded() {
+    for seed in 0..2 {
+        let single = run(false, seed);
+        let multi = run(true, seed);
+
+        assert!(single.tiles() == multi.tiles(), "tiles differ for seed {}", seed);
+        assert!(single.temperatures() == multi.temperatures(), "temperatures differ for seed {}", seed);
+        assert!(single.particles() == multi.particles(), "particles differ for seed {}", seed);
+    }
+}